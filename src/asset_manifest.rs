@@ -0,0 +1,72 @@
+use std::{path::PathBuf, sync::Arc};
+
+use axum::{extract::State, response::{IntoResponse, Response}, Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{templates, AppState};
+
+/// A template, as compiled into the server or last read from disk in dev mode.
+#[derive(Serialize)]
+struct TemplateEntry {
+    name: &'static str,
+    size: usize,
+    sha256: String,
+}
+
+/// A file served from disk, named by its route rather than where it lives.
+#[derive(Serialize)]
+struct FileEntry {
+    route: String,
+    size: u64,
+    sha256: String,
+    modified: Option<DateTime<Utc>>,
+}
+
+/// What this instance ships, so deploy checks can tell which templates and files went out.
+#[derive(Serialize)]
+pub struct AssetManifest {
+    version: &'static str,
+    templates: Vec<TemplateEntry>,
+    files: Vec<FileEntry>,
+    /// Routes whose file isn't there.
+    missing: Vec<String>,
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+impl AssetManifest {
+    /// Hashes every template and every file in `files` (route, path).
+    ///
+    /// Reads the whole files, so it is only meant to run at startup or when templates are reloaded.
+    pub fn build(files: Vec<(String, PathBuf)>) -> Self {
+        let templates = templates::sources()
+            .into_iter()
+            .map(|(name, source)| TemplateEntry { name, size: source.len(), sha256: sha256(source.as_bytes()) })
+            .collect();
+
+        let mut entries = Vec::new();
+        let mut missing = Vec::new();
+        for (route, path) in files {
+            match std::fs::read(&path) {
+                Ok(data) => entries.push(FileEntry {
+                    route,
+                    size: data.len() as u64,
+                    sha256: sha256(&data),
+                    modified: std::fs::metadata(&path).and_then(|metadata| metadata.modified()).ok().map(DateTime::from),
+                }),
+                Err(_) => missing.push(route),
+            }
+        }
+
+        Self { version: env!("CARGO_PKG_VERSION"), templates, files: entries, missing }
+    }
+}
+
+/// `/assets`
+pub async fn asset_manifest(State(state): State<Arc<AppState>>) -> Response {
+    Json(&*state.asset_manifest.read().unwrap_or_else(|e| e.into_inner())).into_response()
+}
@@ -0,0 +1,138 @@
+use std::{path::{Component, Path, PathBuf}, sync::Arc};
+
+use axum::{extract::{Path as UrlPath, State}, http::{header, StatusCode}, response::{IntoResponse, Response}};
+
+use crate::{error::AppError, metrics::metrics, single_flight::SingleFlight, upstream::{self, UpstreamClient}, AppState};
+
+/// Where mirrored files are served from, e.g. `/tetrio/res/flags/fr.png`.
+pub const ASSETS_ROUTE: &str = "/tetrio";
+
+/// TETR.IO rarely changes a file without renaming it, so browsers may keep them for a month.
+const ASSET_CACHE_CONTROL: &str = "public, max-age=2592000";
+
+/// The parts of the CDN the cards use; avatars and banners are not mirrored.
+const MIRRORED_DIRS: &[&str] = &["res", "css"];
+
+type AssetResult = Result<Option<Arc<Vec<u8>>>, Arc<anyhow::Error>>;
+
+/// Keeps a copy of the TETR.IO static files the cards use, so rendering doesn't depend on the CDN.
+///
+/// Files are read from `dir`, which may be seeded ahead of time; missing ones are downloaded once
+/// and written there.
+pub struct AssetMirror {
+    dir: PathBuf,
+    client: UpstreamClient,
+    flights: SingleFlight<AssetResult>,
+}
+
+impl AssetMirror {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, client: UpstreamClient::for_assets(), flights: SingleFlight::default() }
+    }
+
+    /// Where `path` (e.g. `res/flags/fr.png`) is kept, if it is a file the mirror may serve.
+    fn local_path(&self, path: &str) -> Option<PathBuf> {
+        let relative = Path::new(path);
+        let mut components = relative.components();
+
+        let Some(Component::Normal(dir)) = components.next() else {
+            return None;
+        };
+        if !MIRRORED_DIRS.iter().any(|mirrored| dir == *mirrored) {
+            return None;
+        }
+        if !components.all(|component| matches!(component, Component::Normal(_))) {
+            return None;
+        }
+
+        Some(self.dir.join(relative))
+    }
+
+    /// Reads `path` from the mirror, downloading it the first time. `None` when TETR.IO has no such file.
+    pub async fn get(&self, path: &str) -> AssetResult {
+        let Some(local_path) = self.local_path(path) else {
+            return Ok(None);
+        };
+
+        if let Ok(data) = tokio::fs::read(&local_path).await {
+            metrics().record_cache("asset_mirror", true);
+            return Ok(Some(Arc::new(data)));
+        }
+        metrics().record_cache("asset_mirror", false);
+
+        self.flights.run(path, || async {
+            let url = format!("{}/{path}", upstream::urls().cdn);
+            let (status, body) = self.client.get_with_status(&url, None).await.map_err(Arc::new)?;
+
+            if status == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            if !status.is_success() {
+                return Err(Arc::new(anyhow::anyhow!("TETR.IO answered {status} for {path}")));
+            }
+
+            let data = if path.ends_with(".css") {
+                rewrite_css(&body).into_bytes()
+            } else {
+                body.to_vec()
+            };
+
+            if let Err(e) = write_atomically(&local_path, &data).await {
+                tracing::warn!(path, local_path = %local_path.display(), error = %e, "Couldn't mirror an asset");
+            }
+
+            Ok(Some(Arc::new(data)))
+        }).await
+    }
+}
+
+/// Writes next to `path` first, so readers never see half a file.
+async fn write_atomically(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+
+    tokio::fs::write(&partial, data).await?;
+    tokio::fs::rename(&partial, path).await
+}
+
+/// Points the root-relative urls of a stylesheet (fonts, backgrounds...) at the mirror.
+fn rewrite_css(css: &[u8]) -> String {
+    String::from_utf8_lossy(css)
+        .replace("url(/", &format!("url({ASSETS_ROUTE}/"))
+        .replace("url('/", &format!("url('{ASSETS_ROUTE}/"))
+        .replace("url(\"/", &format!("url(\"{ASSETS_ROUTE}/"))
+        // protocol relative urls point elsewhere, undo those
+        .replace(&format!("{ASSETS_ROUTE}//"), "//")
+}
+
+fn content_type(path: &str) -> &'static str {
+    match path.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("css") => "text/css",
+        Some("woff2") => "font/woff2",
+        Some("woff") => "font/woff",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `/tetrio/*path`
+pub async fn tetrio_asset(State(state): State<Arc<AppState>>, UrlPath(path): UrlPath<String>) -> Result<Response, AppError> {
+    match state.asset_mirror.get(&path).await {
+        Ok(Some(data)) => Ok((
+            [(header::CONTENT_TYPE, content_type(&path)), (header::CACHE_CONTROL, ASSET_CACHE_CONTROL)],
+            data.as_ref().clone(),
+        ).into_response()),
+        Ok(None) => Err(AppError::NotFound(format!("No such file: {path}"))),
+        Err(e) => Err(AppError::Upstream(format!("Couldn't fetch {path}: {e}"))),
+    }
+}
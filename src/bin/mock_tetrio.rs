@@ -0,0 +1,119 @@
+//! A stand-in for TETR.IO that answers from the JSON fixtures in `tests/fixtures/tetrio`.
+//!
+//! Point the server at it with
+//! `TETRIO_API_URL=http://127.0.0.1:8090/api TETRIO_CH_API_URL=http://127.0.0.1:8090/api TETRIO_CDN_URL=http://127.0.0.1:8090`.
+//!
+//! A request for `/api/users/taka/summaries` is answered with `api/users/taka/summaries.json`,
+//! query strings are ignored. Anything outside `/api` is served as a plain file.
+//!
+//! Like TETR.IO, users can be looked up by id as well as by name: `user_ids.json` maps ids to the
+//! names their fixtures are stored under.
+
+use std::{collections::HashMap, path::{Component, Path, PathBuf}, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+
+pub const DEFAULT_FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tetrio");
+
+struct Fixtures {
+    root: PathBuf,
+    user_ids: HashMap<String, String>,
+}
+
+impl Fixtures {
+    fn load(root: PathBuf) -> anyhow::Result<Self> {
+        let user_ids = std::fs::read(root.join("user_ids.json"))
+            .map_err(|e| anyhow::anyhow!("Couldn't read {}/user_ids.json: {e}", root.display()))?;
+
+        Ok(Self { user_ids: serde_json::from_slice(&user_ids)?, root })
+    }
+
+    /// Maps a request path onto the fixture answering it, refusing anything that climbs out of the fixtures.
+    fn path(&self, uri: &Uri) -> Option<PathBuf> {
+        let mut relative = PathBuf::from(uri.path().trim_start_matches('/'));
+        if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+            return None;
+        }
+
+        if let Ok(rest) = relative.strip_prefix("api/users") {
+            let mut segments = rest.components();
+            let user = segments.next()?.as_os_str().to_str()?.to_lowercase();
+            let user = self.user_ids.get(&user).cloned().unwrap_or(user);
+
+            let mut path = Path::new("api/users").join(user);
+            if !segments.as_path().as_os_str().is_empty() {
+                path.push(segments.as_path());
+            }
+            relative = path;
+        }
+
+        Some(fixture_file(&self.root, &relative))
+    }
+}
+
+fn fixture_file(root: &Path, relative: &Path) -> PathBuf {
+    let path = root.join(relative);
+    if relative.starts_with("api") {
+        let mut file = path.into_os_string();
+        file.push(".json");
+        PathBuf::from(file)
+    } else {
+        path
+    }
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("jpg") => "image/jpeg",
+        Some("svg") => "image/svg+xml",
+        Some("css") => "text/css",
+        _ => "application/octet-stream",
+    }
+}
+
+async fn fixture(State(fixtures): State<Arc<Fixtures>>, uri: Uri) -> Response {
+    let Some(path) = fixtures.path(&uri) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(body) => ([(header::CONTENT_TYPE, content_type(&path))], body).into_response(),
+        Err(_) if uri.path().starts_with("/api") => (
+            StatusCode::NOT_FOUND,
+            [(header::CONTENT_TYPE, "application/json")],
+            r#"{"success":false,"error":{"msg":"No such fixture"}}"#,
+        ).into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Serves the fixtures found in `root`.
+///
+/// Also used in-process by the server's own tests.
+pub fn router(root: PathBuf) -> anyhow::Result<Router> {
+    Ok(Router::new()
+        .fallback(fixture)
+        .with_state(Arc::new(Fixtures::load(root)?)))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let ip_bind = std::env::var("MOCK_TETRIO_BIND").unwrap_or("127.0.0.1:8090".to_string());
+    let root = std::env::var("MOCK_TETRIO_FIXTURES").map(PathBuf::from).unwrap_or(PathBuf::from(DEFAULT_FIXTURES));
+
+    let app = router(root)?;
+
+    let listener = tokio::net::TcpListener::bind(&ip_bind).await
+        .map_err(|e| anyhow::anyhow!("Couldn't bind to address {ip_bind}: {e}"))?;
+    println!("{ip_bind}");
+
+    axum::serve(listener, app).await?;
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use serde::Serialize;
+
+const WIDTH: f64 = 800.0;
+const PLOT_LEFT: f64 = 60.0;
+const PLOT_RIGHT: f64 = 10.0;
+const PANEL_HEIGHT: f64 = 80.0;
+const PANEL_GAP: f64 = 24.0;
+const MARKERS_HEIGHT: f64 = 24.0;
+
+#[derive(Serialize, Default)]
+pub struct ChartPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Serialize, Default)]
+pub struct ChartPanel {
+    pub label: &'static str,
+    pub y: f64,
+    pub height: f64,
+    pub max: f64,
+    pub left_points: Vec<ChartPoint>,
+    pub right_points: Vec<ChartPoint>,
+}
+
+/// One column of the chart, a round on the tetra card.
+#[derive(Default)]
+pub struct ChartInput {
+    pub label: String,
+    /// How wide the column is relative to the others.
+    pub weight: f64,
+    /// PPS, APM and VS of each side.
+    pub left: [f64; 3],
+    pub right: [f64; 3],
+    pub left_won: bool,
+    pub right_won: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct ChartColumn {
+    pub x: f64,
+    pub width: f64,
+    pub center: f64,
+    pub label: String,
+    pub left_won: bool,
+    pub right_won: bool,
+}
+
+/// Geometry of a PPS, APM and VS chart comparing two players, with one panel per stat.
+#[derive(Serialize, Default)]
+pub struct RoundChart {
+    pub width: f64,
+    pub height: f64,
+    pub plot_left: f64,
+    pub plot_width: f64,
+    pub markers_y: f64,
+    pub panels: Vec<ChartPanel>,
+    pub columns: Vec<ChartColumn>,
+}
+
+/// Seconds in a `m:ss` round time, at least one so every round gets a column.
+pub fn round_seconds(time: &str) -> f64 {
+    let seconds = time.split_once(':')
+        .and_then(|(minutes, seconds)| Some(minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?))
+        .unwrap_or(0);
+
+    seconds.max(1) as f64
+}
+
+fn points(columns: &[ChartColumn], values: &[f64], y: f64, max: f64) -> Vec<ChartPoint> {
+    columns.iter().zip(values)
+        .map(|(column, value)| ChartPoint { x: column.center, y: y + PANEL_HEIGHT - value / max * PANEL_HEIGHT })
+        .collect()
+}
+
+impl RoundChart {
+    pub fn new(inputs: Vec<ChartInput>) -> Self {
+        let plot_width = WIDTH - PLOT_LEFT - PLOT_RIGHT;
+        let total_weight = inputs.iter().map(|input| input.weight).sum::<f64>();
+
+        let mut x = PLOT_LEFT;
+        let columns = inputs.iter().map(|input| {
+            let width = input.weight / total_weight * plot_width;
+            let column = ChartColumn {
+                x,
+                width,
+                center: x + width / 2.0,
+                label: input.label.clone(),
+                left_won: input.left_won,
+                right_won: input.right_won,
+            };
+            x += width;
+            column
+        }).collect::<Vec<_>>();
+
+        let panels = ["PPS", "APM", "VS"].into_iter().enumerate().map(|(index, label)| {
+            let (left, right): (Vec<f64>, Vec<f64>) = inputs.iter().map(|input| (input.left[index], input.right[index])).unzip();
+            let max = left.iter().chain(&right).copied().fold(0.0, f64::max);
+            let max = if max > 0.0 { max * 1.1 } else { 1.0 };
+            let y = PANEL_GAP / 2.0 + index as f64 * (PANEL_HEIGHT + PANEL_GAP);
+
+            ChartPanel {
+                label,
+                y,
+                height: PANEL_HEIGHT,
+                max,
+                left_points: points(&columns, &left, y, max),
+                right_points: points(&columns, &right, y, max),
+            }
+        }).collect::<Vec<_>>();
+
+        let markers_y = PANEL_GAP / 2.0 + panels.len() as f64 * (PANEL_HEIGHT + PANEL_GAP);
+
+        Self {
+            width: WIDTH,
+            height: markers_y + MARKERS_HEIGHT,
+            plot_left: PLOT_LEFT,
+            plot_width,
+            markers_y,
+            panels,
+            columns,
+        }
+    }
+}
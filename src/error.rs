@@ -0,0 +1,138 @@
+use axum::{http::StatusCode, response::{Html, IntoResponse, Response}, Json};
+use minijinja::Value;
+use serde::Serialize;
+
+use crate::templates::{self, Contract};
+
+/// Everything a handler can fail with.
+///
+/// Renders as an error card by default; wrap it in [`JsonError`] for the `/api` routes.
+#[derive(Debug, Clone)]
+pub enum AppError {
+    /// TETR.IO doesn't know this user.
+    UserNotFound(String),
+    /// A replay or record that doesn't exist.
+    NotFound(String),
+    /// TETR.IO couldn't be reached or answered with something unexpected.
+    Upstream(String),
+    /// The request itself is invalid.
+    BadInput(String),
+    /// The replay exists but the requested player isn't in it.
+    MissingPlayer(String),
+    /// A card couldn't be rendered.
+    Render(String),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::UserNotFound(_) => StatusCode::NOT_FOUND,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::BadInput(_) => StatusCode::BAD_REQUEST,
+            AppError::MissingPlayer(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Render(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::UserNotFound(_) => "user_not_found",
+            AppError::NotFound(_) => "not_found",
+            AppError::Upstream(_) => "upstream",
+            AppError::BadInput(_) => "bad_input",
+            AppError::MissingPlayer(_) => "missing_player",
+            AppError::Render(_) => "render",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            AppError::UserNotFound(_) => "UNKNOWN USER",
+            AppError::NotFound(_) => "NOT FOUND",
+            AppError::Upstream(_) => "TETR.IO UNAVAILABLE",
+            AppError::BadInput(_) => "BAD REQUEST",
+            AppError::MissingPlayer(_) => "PLAYER NOT IN REPLAY",
+            AppError::Render(_) => "RENDER FAILED",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::UserNotFound(message)
+            | AppError::NotFound(message)
+            | AppError::Upstream(message)
+            | AppError::BadInput(message)
+            | AppError::MissingPlayer(message)
+            | AppError::Render(message) => message,
+        }
+    }
+
+    fn body(&self) -> ErrorBody<'_> {
+        ErrorBody {
+            kind: self.kind(),
+            status: self.status().as_u16(),
+            title: self.title(),
+            message: self.message(),
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind(), self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<minijinja::Error> for AppError {
+    fn from(value: minijinja::Error) -> Self {
+        AppError::Render(value.to_string())
+    }
+}
+
+#[derive(Serialize, Default)]
+struct ErrorBody<'a> {
+    kind: &'a str,
+    status: u16,
+    title: &'a str,
+    message: &'a str,
+}
+
+pub(crate) fn template_contracts() -> Vec<Contract> {
+    vec![
+        Contract::new("error.html", Value::from_serialize(ErrorBody::default())),
+    ]
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        match templates::render("error.html", &self.body()) {
+            Ok(page) => (status, Html(page)).into_response(),
+            Err(_) => (status, self.to_string()).into_response(),
+        }
+    }
+}
+
+/// An [`AppError`] answered as `{"error": {...}}` instead of an error card.
+#[derive(Debug)]
+pub struct JsonError(pub AppError);
+
+impl From<AppError> for JsonError {
+    fn from(value: AppError) -> Self {
+        JsonError(value)
+    }
+}
+
+impl IntoResponse for JsonError {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct Envelope<'a> {
+            error: ErrorBody<'a>,
+        }
+
+        (self.0.status(), Json(Envelope { error: self.0.body() })).into_response()
+    }
+}
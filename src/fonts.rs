@@ -0,0 +1,235 @@
+use std::{collections::BTreeSet, ops::{Range, RangeInclusive}, path::{Path, PathBuf}, sync::Arc};
+
+use axum::{body::Bytes, extract::{Query, State}, http::{header, Uri}, response::{IntoResponse, Response}};
+use moka::future::Cache;
+use serde::Deserialize;
+
+use crate::{error::AppError, metrics, templates, AppState};
+
+/// How many bytes of fonts and subsets are kept in memory.
+const FONT_CACHE_SIZE: u64 = 32 * 1024 * 1024;
+
+/// A subset's url changes along with the text of the page that loads it.
+const FONT_CACHE_CONTROL: &str = "public, max-age=86400";
+
+const FONT_EXTENSIONS: &[&str] = &[".ttf", ".otf", ".woff", ".woff2"];
+
+/// Kept in every subset, so pages only have to list what's outside of it.
+const ALWAYS_KEPT: RangeInclusive<char> = ' '..='~';
+
+/// Longest `text` a subset can be asked for, anything longer is more than a card shows.
+const MAX_TEXT_CHARS: usize = 4096;
+
+/// Where `source` points to a font, e.g. `/teto/hun2.ttf` in `src:url('/teto/hun2.ttf')`.
+fn font_urls(source: &str) -> Vec<Range<usize>> {
+    let mut urls = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = source[offset..].find("url(") {
+        let start = offset + start + "url(".len();
+        let Some(len) = source[start..].find(')') else {
+            break;
+        };
+        offset = start + len;
+
+        let url = &source[start..offset];
+        let trimmed = url.trim().trim_matches(|c| c == '\'' || c == '"');
+        let path = trimmed.split('?').next().unwrap_or_default();
+        if path.starts_with('/') && FONT_EXTENSIONS.iter().any(|extension| path.ends_with(extension)) {
+            let start = start + url.find(trimmed).unwrap_or_default();
+            urls.push(start..start + trimmed.len());
+        }
+    }
+    urls
+}
+
+/// The file a font route serves, looked up in the fonts directory.
+pub fn file_name(route: &str) -> &str {
+    route.rsplit('/').next().unwrap_or_default()
+}
+
+/// Every font route the templates load.
+pub fn referenced() -> BTreeSet<String> {
+    templates::sources()
+        .iter()
+        .flat_map(|(_, source)| {
+            font_urls(source)
+                .into_iter()
+                .map(|url| source[url].split('?').next().unwrap_or_default().to_string())
+                .collect::<Vec<_>>()
+        })
+        .filter(|route| !route.contains(".."))
+        .collect()
+}
+
+/// The fonts the templates load that aren't in `dir`.
+pub fn missing(dir: &Path) -> Vec<String> {
+    let files = referenced().iter().map(|route| file_name(route).to_string()).collect::<BTreeSet<_>>();
+    files.into_iter().filter(|file| !dir.join(file).is_file()).collect()
+}
+
+/// Decodes `&#233;` and `&#xe9;`, `None` for anything else.
+fn numeric_entity(entity: &str) -> Option<char> {
+    let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => entity.strip_prefix('#')?.parse().ok()?,
+    };
+    char::from_u32(code)
+}
+
+/// `text` without what every subset keeps anyway and what's never drawn, deduplicated and sorted.
+fn extra_chars(text: impl IntoIterator<Item = char>) -> String {
+    text.into_iter()
+        .filter(|c| !ALWAYS_KEPT.contains(c) && !c.is_control())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Every character `page` could display outside of printable ASCII, escaped or not.
+///
+/// Markup is counted too: a glyph too many costs a few bytes, a missing one shows as a blank.
+fn used_chars(page: &str) -> String {
+    let entities = page.split('&')
+        .skip(1)
+        .filter_map(|rest| rest.split_once(';'))
+        .filter_map(|(entity, _)| numeric_entity(entity));
+
+    extra_chars(page.chars().chain(entities))
+}
+
+/// Points the fonts `page` loads to subsets holding only what it shows.
+pub fn subset_font_urls(page: String) -> String {
+    let urls = font_urls(&page).into_iter().filter(|url| !page[url.clone()].contains('?')).collect::<Vec<_>>();
+    if urls.is_empty() {
+        return page;
+    }
+
+    let query = format!("?text={}", urlencoding::encode(&used_chars(&page)));
+    let mut subset = String::with_capacity(page.len() + urls.len() * query.len());
+    let mut copied = 0;
+    for url in urls {
+        subset.push_str(&page[copied..url.end]);
+        subset.push_str(&query);
+        copied = url.end;
+    }
+    subset.push_str(&page[copied..]);
+    subset
+}
+
+/// `data` with the outlines of everything but printable ASCII and `text` dropped.
+///
+/// Glyph ids are left alone, so the character map still works; dropped characters just draw nothing.
+fn subset(data: &[u8], text: &str) -> anyhow::Result<Vec<u8>> {
+    let face = ttf_parser::Face::parse(data, 0)?;
+
+    // glyph 0 is the one drawn for characters the font doesn't have
+    let mut glyphs = vec![0];
+    glyphs.extend(ALWAYS_KEPT.chain(text.chars()).filter_map(|c| face.glyph_index(c)).map(|glyph| glyph.0));
+    glyphs.sort_unstable();
+    glyphs.dedup();
+
+    Ok(subsetter::subset(data, 0, subsetter::Profile::pdf(&glyphs))?)
+}
+
+fn content_type(file: &str) -> &'static str {
+    match file.rsplit('.').next() {
+        Some("otf") => "font/otf",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "font/ttf",
+    }
+}
+
+/// Serves the fonts the templates load, whole or cut down to the characters a page uses.
+pub struct FontServer {
+    dir: PathBuf,
+    cache: Cache<Box<str>, Bytes>,
+}
+
+impl FontServer {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            cache: Cache::builder()
+                .max_capacity(FONT_CACHE_SIZE)
+                .weigher(|_, font: &Bytes| font.len() as u32)
+                .build(),
+        }
+    }
+
+    /// `file`, subset to printable ASCII and `text` when asked to. `None` when the fonts directory doesn't have it.
+    pub async fn get(&self, file: &str, text: Option<&str>) -> Result<Option<Bytes>, Arc<anyhow::Error>> {
+        let path = self.dir.join(file);
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let text = text.map(|text| extra_chars(text.chars()));
+        let key = match &text {
+            Some(text) => format!("{file}?{text}"),
+            None => file.to_string(),
+        };
+
+        metrics::try_get_with("fonts", &self.cache, key.into_boxed_str(), async {
+            let data = tokio::fs::read(&path).await?;
+            let Some(text) = text else {
+                return Ok(Bytes::from(data));
+            };
+
+            let subset = tokio::task::spawn_blocking(move || subset(&data, &text)).await??;
+            anyhow::Ok(Bytes::from(subset))
+        }).await.map(Some)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FontQuery {
+    /// The characters to keep besides printable ASCII; the whole font when missing.
+    text: Option<String>,
+}
+
+/// The fonts the templates load, e.g. `/teto/hun2.ttf?text=…`
+pub async fn font(State(state): State<Arc<AppState>>, uri: Uri, Query(query): Query<FontQuery>) -> Result<Response, AppError> {
+    let file = file_name(uri.path());
+    if query.text.as_ref().is_some_and(|text| text.chars().count() > MAX_TEXT_CHARS) {
+        return Err(AppError::BadInput(format!("Subsets hold at most {MAX_TEXT_CHARS} characters")));
+    }
+
+    match state.fonts.get(file, query.text.as_deref()).await {
+        Ok(Some(font)) => Ok(([(header::CONTENT_TYPE, content_type(file)), (header::CACHE_CONTROL, FONT_CACHE_CONTROL)], font).into_response()),
+        Ok(None) => Err(AppError::NotFound(format!("{file} isn't installed"))),
+        Err(e) => Err(AppError::Render(format!("Couldn't subset {file}: {e}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_fonts_templates_load() {
+        let source = "@font-face{src:url('/teto/hun2.ttf');font-family:HUN} a{background:url(/bg.png)} b{src:url( \"/x.woff2?v=1\" )}";
+        let urls = font_urls(source).into_iter().map(|url| &source[url]).collect::<Vec<_>>();
+        assert_eq!(urls, ["/teto/hun2.ttf", "/x.woff2?v=1"]);
+
+        let referenced = referenced();
+        assert!(referenced.contains("/teto/hun2.ttf"), "{referenced:?}");
+        assert!(referenced.contains("/tetra/hun2.ttf"), "{referenced:?}");
+    }
+
+    #[test]
+    fn pages_ask_for_what_they_show() {
+        let page = String::from("<style>@font-face{src:url('/teto/hun2.ttf')}</style><p>Zoë &#x2605; &#9733; &amp; é</p>");
+        assert_eq!(subset_font_urls(page), "<style>@font-face{src:url('/teto/hun2.ttf?text=%C3%A9%C3%AB%E2%98%85')}</style><p>Zoë &#x2605; &#9733; &amp; é</p>");
+
+        // already pinned urls are left alone
+        let page = String::from("@font-face{src:url('/x.ttf?text=a')} ë");
+        assert_eq!(subset_font_urls(page.clone()), page);
+    }
+
+    #[test]
+    fn missing_fonts_are_reported_once() {
+        let dir = std::env::temp_dir().join(format!("tetrio_html_server_no_fonts_{}", std::process::id()));
+        assert_eq!(missing(&dir), ["hun2.ttf"]);
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use axum::{extract::{Query, State}, response::Html, Json};
+use common::{Average, LeagueRecord};
+use minijinja::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::{chart::{ChartInput, RoundChart}, error::{AppError, JsonError}, nerd_stats::NerdStats, templates::{self, Contract}, tetra::{self, LeagueReplay}, AppState, LeagueLeaderboard};
+
+#[derive(Deserialize)]
+pub struct H2hParam {
+    user_id: String,
+    opponent_id: String,
+}
+
+pub(crate) fn template_contracts() -> Vec<Contract> {
+    vec![
+        Contract::new("tetra/h2h.html", Value::from_serialize(H2hPage::default())),
+    ]
+}
+
+#[derive(Serialize, Default)]
+pub struct H2hMatch {
+    pub replay_id: String,
+    pub played_date: String,
+    pub played_time: String,
+    pub left_score: u32,
+    pub right_score: u32,
+    pub won: bool,
+    pub left_stats: NerdStats,
+    pub right_stats: NerdStats,
+}
+
+/// Every league match two players played against each other, from the first player's side.
+#[derive(Serialize, Default)]
+pub struct H2hPage {
+    pub left_username: String,
+    pub right_username: String,
+    pub wins: usize,
+    pub losses: usize,
+    pub left_rounds: u32,
+    pub right_rounds: u32,
+    /// Stats averaged over every match.
+    pub left_average: NerdStats,
+    pub right_average: NerdStats,
+    /// Oldest match first.
+    pub matches: Vec<H2hMatch>,
+    pub chart: RoundChart,
+}
+
+fn average_stats(averages: &[&Average]) -> NerdStats {
+    if averages.is_empty() {
+        return NerdStats::default();
+    }
+
+    let count = averages.len() as f64;
+    NerdStats::new(
+        averages.iter().map(|average| average.pps).sum::<f64>() / count,
+        averages.iter().map(|average| average.apm).sum::<f64>() / count,
+        averages.iter().map(|average| average.vs).sum::<f64>() / count,
+    )
+}
+
+impl H2hPage {
+    fn new(mut records: Vec<(String, LeagueRecord, chrono::DateTime<chrono::Utc>)>) -> Self {
+        records.sort_by_key(|(_, _, timestamp)| *timestamp);
+
+        let lefts = records.iter().map(|(_, record, _)| &record.averages.left).collect::<Vec<_>>();
+        let rights = records.iter().map(|(_, record, _)| &record.averages.right).collect::<Vec<_>>();
+
+        let matches = records.iter().map(|(replay_id, record, timestamp)| {
+            let left = &record.averages.left;
+            let right = &record.averages.right;
+
+            H2hMatch {
+                replay_id: replay_id.clone(),
+                played_date: timestamp.format("%d/%m/%Y").to_string(),
+                played_time: timestamp.format("%H:%M:%S").to_string(),
+                left_score: left.score,
+                right_score: right.score,
+                won: left.score > right.score,
+                left_stats: NerdStats::new(left.pps, left.apm, left.vs),
+                right_stats: NerdStats::new(right.pps, right.apm, right.vs),
+            }
+        }).collect::<Vec<_>>();
+
+        let chart = RoundChart::new(matches.iter().map(|h2h_match| ChartInput {
+            label: h2h_match.played_date.clone(),
+            weight: 1.0,
+            left: [h2h_match.left_stats.pps, h2h_match.left_stats.apm, h2h_match.left_stats.vs],
+            right: [h2h_match.right_stats.pps, h2h_match.right_stats.apm, h2h_match.right_stats.vs],
+            left_won: h2h_match.won,
+            right_won: !h2h_match.won,
+        }).collect());
+
+        Self {
+            // the latest usernames, in case someone got renamed
+            left_username: lefts.last().map(|left| left.username.clone()).unwrap_or_default(),
+            right_username: rights.last().map(|right| right.username.clone()).unwrap_or_default(),
+            wins: matches.iter().filter(|h2h_match| h2h_match.won).count(),
+            losses: matches.iter().filter(|h2h_match| !h2h_match.won).count(),
+            left_rounds: lefts.iter().map(|left| left.score).sum(),
+            right_rounds: rights.iter().map(|right| right.score).sum(),
+            left_average: average_stats(&lefts),
+            right_average: average_stats(&rights),
+            matches,
+            chart,
+        }
+    }
+}
+
+async fn fetch_h2h(state: &AppState, params: &H2hParam) -> Result<H2hPage, AppError> {
+    let mut player_id = None;
+    let mut replay_ids = Vec::new();
+    for leaderboard in [LeagueLeaderboard::Recent, LeagueLeaderboard::Top] {
+        let packet = match state.tetrio_http_client.fetch_user_league_records(&params.user_id, leaderboard, state.settings.limits.h2h_records as i64).await {
+            Ok(packet) => packet,
+            Err(e) => return Err(AppError::Upstream(format!("Couldn't fetch league records: {e:?}")))
+        };
+
+        let Some(data) = &packet.data else {
+            return Err(AppError::UserNotFound(format!("No user named {}", params.user_id)))
+        };
+
+        for entry in &data.entries {
+            // replays only know players by id, `user_id` may be a username
+            player_id.get_or_insert_with(|| entry.user.id.clone());
+            if entry.is_duel() && entry.is_against(&params.opponent_id) && !replay_ids.contains(&entry.replayid) {
+                replay_ids.push(entry.replayid.clone());
+            }
+        }
+    }
+
+    let player_id = player_id.unwrap_or_else(|| params.user_id.clone());
+    let replays = state.tetrio_http_client.fetch_tetrio_replays(&replay_ids, &state.settings.tetrio.token).await;
+
+    let mut records = Vec::new();
+    for (replay_id, replay_data) in replay_ids.into_iter().zip(replays) {
+        let replay_data = match replay_data {
+            Ok(replay_data) => replay_data,
+            Err(e) => return Err(AppError::Upstream(format!("Couldn't fetch replay data: {e}"))),
+        };
+
+        let Some(data) = &replay_data.game else {
+            continue;
+        };
+
+        if let LeagueReplay::Duel(league_record) = tetra::generate_league_replay(data, &replay_id, &player_id)? {
+            records.push((replay_id, league_record, data.ts));
+        }
+    }
+
+    if records.is_empty() {
+        return Err(AppError::NotFound(format!("No league matches between {} and {} in their recent and top records", params.user_id, params.opponent_id)));
+    }
+
+    Ok(H2hPage::new(records))
+}
+
+/// `/league_h2h`
+pub async fn league_h2h(State(state): State<Arc<AppState>>, Query(params): Query<H2hParam>) -> Result<Html<String>, AppError> {
+    let page = fetch_h2h(&state, &params).await?;
+    Ok(Html(templates::render("tetra/h2h.html", &page)?))
+}
+
+/// `/api/league_h2h`
+pub async fn league_h2h_json(State(state): State<Arc<AppState>>, Query(params): Query<H2hParam>) -> Result<Json<H2hPage>, JsonError> {
+    Ok(Json(fetch_h2h(&state, &params).await?))
+}
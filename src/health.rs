@@ -0,0 +1,78 @@
+use std::{sync::{atomic::Ordering, Arc}, time::Duration};
+
+use axum::{extract::State, http::StatusCode, response::{IntoResponse, Response}, Json};
+use serde::Serialize;
+
+use crate::{upstream, AppState};
+
+/// How recently TETR.IO must have answered for `/ready` to trust it without asking again.
+const UPSTREAM_FRESHNESS: Duration = Duration::from_secs(5 * 60);
+
+/// `/ready` is polled by orchestrators with short timeouts of their own.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Serialize)]
+struct Check {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<Result<(), String>> for Check {
+    fn from(value: Result<(), String>) -> Self {
+        match value {
+            Ok(()) => Check { ok: true, error: None },
+            Err(error) => Check { ok: false, error: Some(error) },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    serving: Check,
+    redis: Check,
+    tetrio: Check,
+}
+
+async fn check_redis(state: &AppState) -> Result<(), String> {
+    state.tetrio_http_client.redis_cache.ping().await.map_err(|e| format!("Couldn't ping redis: {e}"))
+}
+
+async fn check_tetrio(state: &AppState) -> Result<(), String> {
+    let client = &state.tetrio_http_client.upstream;
+    if client.last_success().is_some_and(|at| at.elapsed() < UPSTREAM_FRESHNESS) {
+        return Ok(());
+    }
+
+    // nothing recent to go by, so ask once for the bot's own account, which checks the token too
+    let url = format!("{}/users/me", upstream::urls().api);
+    match client.probe(&url, Some(&state.settings.tetrio.token), PROBE_TIMEOUT).await {
+        Ok((status, body)) if status.is_success() => {
+            let success = serde_json::from_slice::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|packet| packet.get("success")?.as_bool());
+
+            match success {
+                Some(false) => Err(String::from("TETR.IO rejected the bot's token")),
+                _ => Ok(()),
+            }
+        }
+        Ok((status, _)) => Err(format!("TETR.IO answered {status} for the bot's account")),
+        Err(e) => Err(format!("Couldn't reach TETR.IO: {e:#}")),
+    }
+}
+
+/// `/ready`: whether this instance can serve cards right now, `503` with the failing checks when it can't.
+pub async fn ready(State(state): State<Arc<AppState>>) -> Response {
+    let serving = if state.draining.load(Ordering::Relaxed) { Err(String::from("Shutting down")) } else { Ok(()) };
+    let (redis, tetrio) = tokio::join!(check_redis(&state), check_tetrio(&state));
+    let readiness = Readiness { serving: serving.into(), redis: redis.into(), tetrio: tetrio.into() };
+
+    let status = if readiness.serving.ok && readiness.redis.ok && readiness.tetrio.ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(readiness)).into_response()
+}
@@ -0,0 +1,129 @@
+use std::{io::Cursor, sync::Arc, time::Duration};
+
+use axum::{extract::{Path, State}, http::{header, StatusCode}, response::{IntoResponse, Response}};
+use image::{codecs::webp::WebPEncoder, imageops::FilterType};
+use moka::future::Cache;
+
+use crate::{error::AppError, metrics, upstream::{self, UpstreamClient}, AppState};
+
+pub const UNKNOWN_AVATAR_PATH: &str = "/teto/unkown_avatar.webp";
+pub const UNKNOWN_AVATAR: &[u8] = include_bytes!("../assets/teto/unkown_avatar.webp");
+
+/// A revision never changes once uploaded, so browsers may keep the images forever.
+const IMAGE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// How many bytes of re-encoded images are kept in memory.
+const IMAGE_CACHE_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Clone, Copy)]
+pub enum UserImage {
+    Avatar,
+    Banner,
+}
+
+impl UserImage {
+    fn name(self) -> &'static str {
+        match self {
+            UserImage::Avatar => "avatar",
+            UserImage::Banner => "banner",
+        }
+    }
+
+    /// Twice the size the cards show them at, so they stay sharp on dense screens.
+    fn size(self) -> (u32, u32) {
+        match self {
+            UserImage::Avatar => (280, 280),
+            UserImage::Banner => (1800, 360),
+        }
+    }
+}
+
+/// Where the card gets the avatar uploaded as revision `rev`.
+pub fn avatar_url(user_id: &str, rev: i64) -> String {
+    format!("/img/avatar/{user_id}/{rev}")
+}
+
+/// Where the card gets the banner uploaded as revision `rev`.
+pub fn banner_url(user_id: &str, rev: i64) -> String {
+    format!("/img/banner/{user_id}/{rev}")
+}
+
+type ImageResult = Result<Option<Arc<Vec<u8>>>, Arc<anyhow::Error>>;
+
+/// Fetches avatars and banners from TETR.IO once, cropped to the card's dimensions and re-encoded to WebP.
+pub struct ImageProxy {
+    client: UpstreamClient,
+    cache: Cache<Box<str>, Option<Arc<Vec<u8>>>>,
+}
+
+impl Default for ImageProxy {
+    fn default() -> Self {
+        Self {
+            client: UpstreamClient::for_assets(),
+            cache: Cache::builder()
+                .max_capacity(IMAGE_CACHE_SIZE)
+                .weigher(|_, image: &Option<Arc<Vec<u8>>>| image.as_ref().map_or(1, |image| image.len() as u32))
+                .time_to_idle(Duration::from_secs(24 * 60 * 60))
+                .build(),
+        }
+    }
+}
+
+/// Crops `data` to fill `size`, like the cards' `xMidYMid slice`, and encodes it to WebP.
+fn resize(data: &[u8], (width, height): (u32, u32)) -> anyhow::Result<Vec<u8>> {
+    let image = image::load_from_memory(data)?
+        .resize_to_fill(width, height, FilterType::Lanczos3)
+        .into_rgba8();
+
+    let mut webp = Vec::new();
+    image.write_with_encoder(WebPEncoder::new_lossless(Cursor::new(&mut webp)))?;
+    Ok(webp)
+}
+
+impl ImageProxy {
+    /// The image `user_id` uploaded as revision `rev`, `None` when TETR.IO doesn't have it.
+    pub async fn get(&self, kind: UserImage, user_id: &str, rev: u64) -> ImageResult {
+        // ids are hexadecimal, anything else can't be on the CDN
+        if user_id.is_empty() || !user_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Ok(None);
+        }
+
+        let key = format!("{}:{user_id}:{rev}", kind.name()).into_boxed_str();
+        metrics::try_get_with("images", &self.cache, key, async {
+            let url = format!("{}/user-content/{}s/{user_id}.jpg?rv={rev}", upstream::urls().cdn, kind.name());
+            let (status, body) = self.client.get_with_status(&url, None).await?;
+
+            if status == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            if !status.is_success() {
+                anyhow::bail!("TETR.IO answered {status} for the {} of {user_id}", kind.name());
+            }
+
+            let webp = tokio::task::spawn_blocking(move || resize(&body, kind.size())).await??;
+            Ok(Some(Arc::new(webp)))
+        }).await
+    }
+}
+
+fn webp_response(data: Vec<u8>) -> Response {
+    ([(header::CONTENT_TYPE, "image/webp"), (header::CACHE_CONTROL, IMAGE_CACHE_CONTROL)], data).into_response()
+}
+
+/// `/img/avatar/:user_id/:rev`
+pub async fn avatar(State(state): State<Arc<AppState>>, Path((user_id, rev)): Path<(String, u64)>) -> Result<Response, AppError> {
+    match state.image_proxy.get(UserImage::Avatar, &user_id, rev).await {
+        Ok(Some(image)) => Ok(webp_response(image.as_ref().clone())),
+        Ok(None) => Ok(webp_response(UNKNOWN_AVATAR.to_vec())),
+        Err(e) => Err(AppError::Upstream(format!("Couldn't fetch the avatar of {user_id}: {e}"))),
+    }
+}
+
+/// `/img/banner/:user_id/:rev`
+pub async fn banner(State(state): State<Arc<AppState>>, Path((user_id, rev)): Path<(String, u64)>) -> Result<Response, AppError> {
+    match state.image_proxy.get(UserImage::Banner, &user_id, rev).await {
+        Ok(Some(image)) => Ok(webp_response(image.as_ref().clone())),
+        Ok(None) => Err(AppError::NotFound(format!("{user_id} has no banner {rev}"))),
+        Err(e) => Err(AppError::Upstream(format!("Couldn't fetch the banner of {user_id}: {e}"))),
+    }
+}
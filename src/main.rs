@@ -1,9 +1,30 @@
+mod asset_manifest;
+mod asset_mirror;
+mod chart;
+mod error;
+mod fonts;
+mod h2h;
+mod health;
+mod image_proxy;
+mod metrics;
+mod nerd_stats;
+mod page_cache;
+mod render;
+mod redis_cache;
+mod session;
+mod settings;
+mod single_flight;
+mod templates;
 mod teto;
 pub mod tetra;
+mod upstream;
+
+#[cfg(test)]
+mod tests;
 
 use common::Error;
 
-use std::{time::Duration, sync::Arc, fs::DirEntry};
+use std::{future::Future, path::PathBuf, time::Duration, sync::{atomic::{AtomicBool, Ordering}, Arc, RwLock}};
 
 use axum::{
     response::IntoResponse,
@@ -11,200 +32,480 @@ use axum::{
 };
 
 
-use serde::{Deserialize, Serialize};
-use tetrio_api::{http::clients::reqwest_client::{RedisReqwestClient, ReqwestClient}, models::common::{APIfloat, APIint, APIstring}};
-use itertools::Itertools;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tetrio_api::models::{common::{APIfloat, APIint, APIstring}, packet::Packet, users::{summaries::AllSummaries, user_info::UserInfo}};
 
+use asset_manifest::AssetManifest;
+use asset_mirror::AssetMirror;
+use image_proxy::ImageProxy;
+use futures::StreamExt;
 use moka::future::Cache;
+use tokio::{net::TcpListener, sync::{oneshot, watch}};
+use tracing_subscriber::EnvFilter;
+use redis_cache::RedisCache;
+use single_flight::SingleFlight;
+use settings::{AssetSettings, CacheSettings, Settings};
+use upstream::UpstreamClient;
 
-use crate::tetra::{league_recent_test, league_recent, league_replay, league_replay_from_data};
+use crate::tetra::{league_recent_test, league_recent, league_recent_json, league_replay, league_replay_json, league_replay_from_data, league_replay_from_data_json, league_replay_png};
 
 
 
-struct TetrioCachedClient {
-   tetrio_replays_cache: Cache<Box<str>, Arc<GameReplayPacket>>,
+/// The personal league leaderboards a player's records can be read from.
+#[derive(Clone, Copy, Debug)]
+pub enum LeagueLeaderboard {
+    Recent,
+    Top,
+}
+
+impl LeagueLeaderboard {
+    fn path(self) -> &'static str {
+        match self {
+            LeagueLeaderboard::Recent => "recent",
+            LeagueLeaderboard::Top => "top",
+        }
+    }
+}
+
+/// Keeps channel API packets for as long as TETR.IO says they are fresh, but no longer than `max`.
+struct PacketExpiry {
+    max: Duration,
 }
 
-impl Default for TetrioCachedClient {
-    fn default() -> Self {
-        Self { tetrio_replays_cache: Cache::builder().time_to_live(Duration::from_secs(15 * 60)).build()}
+impl<T> moka::Expiry<Box<str>, Arc<Packet<T>>> for PacketExpiry {
+    fn expire_after_create(&self, _key: &Box<str>, packet: &Arc<Packet<T>>, _created_at: std::time::Instant) -> Option<Duration> {
+        Some(fresh_for(packet, self.max))
     }
 }
 
+/// How much longer TETR.IO says `packet` is fresh for, but no longer than `max`.
+fn fresh_for<T>(packet: &Packet<T>, max: Duration) -> Duration {
+    let fresh_for = packet.cache.as_ref()
+        .map(|cache| cache.cached_until - chrono::Utc::now().timestamp_millis())
+        .unwrap_or(60 * 1000);
+
+    Duration::from_millis(fresh_for.max(0) as u64).min(max)
+}
+
+/// How many replays a single page fetches at once.
+///
+/// The rate limit spaces them out anyway; this keeps one page from queueing all of its fetches ahead of everyone else's.
+const REPLAY_FETCHES_AT_ONCE: usize = 4;
+
+fn packet_cache<T: Send + Sync + 'static>(max: Duration) -> Cache<Box<str>, Arc<Packet<T>>> {
+    Cache::builder().expire_after(PacketExpiry { max }).build()
+}
+
+struct TetrioCachedClient {
+   tetrio_replays_cache: Cache<Box<str>, Arc<GameReplayPacket>>,
+   redis_cache: RedisCache,
+   replay_redis_ttl: Duration,
+   max_packet_ttl: Duration,
+   user_info_cache: Cache<Box<str>, Arc<Packet<UserInfo>>>,
+   user_summaries_cache: Cache<Box<str>, Arc<Packet<AllSummaries>>>,
+   league_records_cache: Cache<Box<str>, Arc<Packet<LeagueRecords>>>,
+   upstream: Arc<UpstreamClient>,
+}
+
 impl TetrioCachedClient {
+    pub fn new(redis_client: redis::Client, upstream: Arc<UpstreamClient>, cache: &CacheSettings) -> Self {
+        Self {
+            tetrio_replays_cache: Cache::builder().time_to_live(cache.replay_ttl()).build(),
+            redis_cache: RedisCache::new(redis_client),
+            replay_redis_ttl: cache.replay_redis_ttl(),
+            max_packet_ttl: cache.max_packet_ttl(),
+            user_info_cache: packet_cache(cache.max_packet_ttl()),
+            user_summaries_cache: packet_cache(cache.max_packet_ttl()),
+            league_records_cache: packet_cache(cache.max_packet_ttl()),
+            upstream,
+        }
+    }
+
     pub async fn me(&self,  tetrio_token: &str) -> anyhow::Result<String> {
-        let result =reqwest::Client::new()
-        .get(&format!("https://tetr.io/api/users/me"))
-        .header("Authorization", tetrio_token)
-        .header("Accept", "application/json")
-        .send()
-        .await?
-        .text()
-        .await?;
+        let result = self.upstream.get(&format!("{}/users/me", upstream::urls().api), Some(tetrio_token)).await?;
 
-        return Ok(result)
+        Ok(String::from_utf8_lossy(&result).into_owned())
     }
 
+    /// Looks the replay up in memory, then in redis, and only then asks TETR.IO.
+    ///
+    /// Concurrent calls for the same replay wait on a single lookup.
     pub async fn fetch_tetrio_replay(&self, replay_id: &str, tetrio_token: &str) -> anyhow::Result<Arc<GameReplayPacket>> {
         let replay_id = replay_id.to_string().into_boxed_str();
-        if let Some(data) = self.tetrio_replays_cache.get(&replay_id).await {
-            return Ok(Arc::clone(&data));
-        }
+        metrics::try_get_with("replays", &self.tetrio_replays_cache, replay_id.clone(), self.fetch_uncached_tetrio_replay(&replay_id, tetrio_token))
+            .await
+            .map_err(|e| anyhow::anyhow!("{e:#}"))
+    }
 
-        let result =reqwest::Client::new()
-            .get(&format!("https://tetr.io/api/games/{}", replay_id))
-            .header("Authorization", tetrio_token)
-            .header("Accept", "application/json")
-            .send()
-            .await?
-            .json::<GameReplayPacket>()
+    /// Fetches every replay in `replay_ids`, a few at a time, answering in the same order.
+    pub async fn fetch_tetrio_replays(&self, replay_ids: &[String], tetrio_token: &str) -> Vec<anyhow::Result<Arc<GameReplayPacket>>> {
+        let fetches = replay_ids.iter().map(|replay_id| self.fetch_tetrio_replay(replay_id, tetrio_token)).collect::<Vec<_>>();
+        futures::stream::iter(fetches)
+            .buffered(REPLAY_FETCHES_AT_ONCE)
+            .collect()
             .await
-            .map(Arc::new)
-            ?;
+    }
+
+    async fn fetch_uncached_tetrio_replay(&self, replay_id: &str, tetrio_token: &str) -> anyhow::Result<Arc<GameReplayPacket>> {
+        if let Some(payload) = self.redis_cache.get(redis_cache::Kind::Replay, replay_id).await {
+            match serde_json::from_slice::<GameReplayPacket>(&payload) {
+                Ok(result) => return Ok(Arc::new(result)),
+                Err(e) => tracing::warn!(replay_id, error = %e, "Couldn't parse a cached replay"),
+            }
+        }
+
+        let payload = self.upstream.get(&format!("{}/games/{}", upstream::urls().api, replay_id), Some(tetrio_token)).await?;
+
+        let result = Arc::new(serde_json::from_slice::<GameReplayPacket>(&payload)?);
+
+        // missing replays might just not be processed yet, so only keep them in memory
+        if result.game.is_some() {
+            self.redis_cache.set(redis_cache::Kind::Replay, replay_id, &payload, self.replay_redis_ttl).await;
+        }
 
-        self.tetrio_replays_cache.insert(replay_id, Arc::clone(&result)).await;
         Ok(result)
     }
+
+    pub async fn fetch_user_info(&self, user_id: &str) -> anyhow::Result<Arc<Packet<UserInfo>>> {
+        let url = format!("{}/users/{}", upstream::urls().ch_api, urlencoding::encode(&user_id.to_lowercase()));
+        self.fetch_packet("user_info", &self.user_info_cache, url).await
+    }
+
+    pub async fn fetch_user_summaries(&self, user_id: &str) -> anyhow::Result<Arc<Packet<AllSummaries>>> {
+        let url = format!("{}/users/{}/summaries", upstream::urls().ch_api, urlencoding::encode(&user_id.to_lowercase()));
+        self.fetch_packet("user_summaries", &self.user_summaries_cache, url).await
+    }
+
+    pub async fn fetch_user_league_records(&self, user_id: &str, leaderboard: LeagueLeaderboard, limit: i64) -> anyhow::Result<Arc<Packet<LeagueRecords>>> {
+        let url = format!(
+            "{}/users/{}/records/league/{}?limit={limit}",
+            upstream::urls().ch_api,
+            urlencoding::encode(&user_id.to_lowercase()),
+            leaderboard.path()
+        );
+        self.fetch_packet("league_records", &self.league_records_cache, url).await
+    }
+
+    /// Reads a channel API packet, keyed on its url, looking it up in memory, then in redis, and only then asking TETR.IO.
+    async fn fetch_packet<T>(&self, cache_name: &str, cache: &Cache<Box<str>, Arc<Packet<T>>>, url: String) -> anyhow::Result<Arc<Packet<T>>>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        let key = url.clone().into_boxed_str();
+        metrics::try_get_with(cache_name, cache, key, async {
+            if let Some(payload) = self.redis_cache.get(redis_cache::Kind::Packet, &url).await {
+                match serde_json::from_slice::<Packet<T>>(&payload) {
+                    Ok(packet) => return Ok(Arc::new(packet)),
+                    Err(e) => tracing::warn!(url, error = %e, "Couldn't parse a cached packet"),
+                }
+            }
+
+            let payload = self.upstream.get(&url, None).await?;
+            let packet = serde_json::from_slice::<Packet<T>>(&payload)?;
+
+            // kept for as long as TETR.IO says, so every replica agrees on when it goes stale
+            self.redis_cache.set(redis_cache::Kind::Packet, &url, &payload, fresh_for(&packet, self.max_packet_ttl)).await;
+            anyhow::Ok(Arc::new(packet))
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("{e:#}"))
+    }
 }
 
 
-pub struct AppState<'a> {
-    tetrio_token: String,
+pub struct AppState {
+    settings: Settings,
     tetrio_http_client: Arc<TetrioCachedClient>,
-    api_http_client: Arc<RedisReqwestClient<'a>>,
+    asset_mirror: Arc<AssetMirror>,
+    image_proxy: Arc<ImageProxy>,
+    png_renderer: render::PngRenderer,
+    fonts: fonts::FontServer,
+    page_cache: page_cache::PageCache,
+    asset_manifest: RwLock<AssetManifest>,
+    /// Set once the server is shutting down, so `/ready` sends traffic elsewhere.
+    draining: AtomicBool,
+    user_info_flights: SingleFlight<UpstreamResult<UserInfo>>,
+    user_summaries_flights: SingleFlight<UpstreamResult<AllSummaries>>,
+    /// Last good answers, served while TETR.IO is failing.
+    stale_user_info: Cache<Box<str>, Arc<Packet<UserInfo>>>,
+    stale_user_summaries: Cache<Box<str>, Arc<Packet<AllSummaries>>>,
+}
+
+type UpstreamResult<T> = Result<Arc<Packet<T>>, Arc<anyhow::Error>>;
+
+impl AppState {
+    pub fn new(settings: Settings, redis_client: redis::Client) -> Self {
+        let upstream = Arc::new(UpstreamClient::for_api(settings.tetrio.requests_per_second, settings.tetrio.burst));
+        let asset_mirror = Arc::new(AssetMirror::new(settings.assets.mirror_dir.clone()));
+        let image_proxy = Arc::new(ImageProxy::default());
+        Self {
+            tetrio_http_client: Arc::new(TetrioCachedClient::new(redis_client, upstream, &settings.cache)),
+            png_renderer: render::PngRenderer::new(Arc::clone(&asset_mirror), Arc::clone(&image_proxy), &settings.assets.fonts_dir),
+            fonts: fonts::FontServer::new(settings.assets.fonts_dir.clone()),
+            page_cache: page_cache::PageCache::new(settings.cache.page_ttl()),
+            asset_manifest: RwLock::new(AssetManifest::build(served_files(&settings.assets))),
+            draining: AtomicBool::new(false),
+            asset_mirror,
+            image_proxy,
+            user_info_flights: Default::default(),
+            user_summaries_flights: Default::default(),
+            stale_user_info: Cache::builder().time_to_live(settings.cache.stale_ttl()).build(),
+            stale_user_summaries: Cache::builder().time_to_live(settings.cache.stale_ttl()).build(),
+            settings,
+        }
+    }
+
+    /// Drops everything rendered with the previous templates.
+    fn templates_reloaded(&self) {
+        self.page_cache.clear();
+        self.png_renderer.clear();
+        *self.asset_manifest.write().unwrap_or_else(|e| e.into_inner()) = AssetManifest::build(served_files(&self.settings.assets));
+    }
+
+    /// Concurrent calls for the same user share one request.
+    pub async fn fetch_user_info(&self, user_id: &str) -> UpstreamResult<UserInfo> {
+        let key = user_id.to_lowercase();
+        self.user_info_flights.run(&key, || async {
+            upstream::with_stale_fallback(&self.stale_user_info, &key, self.tetrio_http_client.fetch_user_info(user_id)).await
+        }).await
+    }
+
+    /// Concurrent calls for the same user share one request.
+    pub async fn fetch_user_summaries(&self, user_id: &str) -> UpstreamResult<AllSummaries> {
+        let key = user_id.to_lowercase();
+        self.user_summaries_flights.run(&key, || async {
+            upstream::with_stale_fallback(&self.stale_user_summaries, &key, self.tetrio_http_client.fetch_user_summaries(user_id)).await
+        }).await
+    }
 }
 
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // initialize tracing
-    dotenvy::dotenv().expect("Couldn't read .env file");
-    tracing_subscriber::fmt::init();
-
-    let ip_bind = std::env::var("BIND_URL").unwrap_or("0.0.0.0:80".to_string());
-    println!("{ip_bind}");
-    let tetrio_token = std::env::var("TETRIO_API_TOKEN").expect("Couldn't get tetrio token");
-    let redis_url = std::env::var("REDIS_URL").expect("Couldn't get tetrio token");
-    let client = redis::Client::open(redis_url)?;
-    let state = AppState {tetrio_token, tetrio_http_client: Default::default(), api_http_client: Arc::new(
-        RedisReqwestClient::new(
-            ReqwestClient::default(),
-            tetrio_api::http::caches::redis_cache::RedisCache { client: std::borrow::Cow::Owned(client) }
-        ))
+    // a missing .env is fine, the environment may be set some other way
+    if let Err(e) = dotenvy::dotenv() {
+        if !e.not_found() {
+            anyhow::bail!("Couldn't read .env file: {e}");
+        }
+    }
+    // info and up, unless `RUST_LOG` says otherwise
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+    let settings = Settings::load()?;
+    upstream::init_urls(settings.tetrio.urls());
+    if settings.features.dev_templates {
+        templates::init_from_dir(&settings.assets.dir)?;
+    } else {
+        templates::init()?;
+    }
+
+    // the fonts can't be shipped along with the server, see assets/fonts/README.md; without them
+    // pages fall back to the browser's fonts, and their font routes answer 404
+    let missing_fonts = fonts::missing(&settings.assets.fonts_dir);
+    if !missing_fonts.is_empty() {
+        tracing::warn!(
+            fonts_dir = %settings.assets.fonts_dir.display(),
+            missing = %missing_fonts.join(", "),
+            "The templates load fonts that aren't installed, cards will use fallback fonts",
+        );
+    }
+
+    let client = redis::Client::open(settings.redis.url.as_str())?;
+    let state = Arc::new(AppState::new(settings, client));
+
+    if state.settings.features.dev_templates {
+        let reloaded = Arc::clone(&state);
+        templates::watch(state.settings.assets.dir.clone(), move || reloaded.templates_reloaded());
+    }
+
+    // both addresses are taken before anything is served, so a busy port stops the server right away
+    let listener = bind(&state.settings.server.bind).await?;
+    let health_listener = bind(&state.settings.server.health_bind).await?;
+    tracing::info!(
+        bind = %state.settings.server.bind,
+        health_bind = %state.settings.server.health_bind,
+        version = env!("CARGO_PKG_VERSION"),
+        dev_templates = state.settings.features.dev_templates,
+        "Serving cards",
+    );
+
+    serve(state, listener, health_listener, shutdown_signal()).await?;
+    tracing::info!("Stopped");
+
+    Ok(())
+}
+
+/// How long requests still running get to finish once the server is asked to stop.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+async fn bind(address: &str) -> anyhow::Result<TcpListener> {
+    TcpListener::bind(address).await.map_err(|e| {
+        anyhow::anyhow!(Error(format!("Couldn't bind to address {address}: {e}")))
+    })
+}
+
+/// Resolves on the first SIGTERM or Ctrl-C.
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "Couldn't listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
     };
 
-    tokio::spawn(async {
-        let ip_bind = std::env::var("HEALTH_URL").unwrap_or("0.0.0.0:8080".to_string());
-        println!("{ip_bind}");
-    
-        // let origins = [
-        //     "https://health.takathedinosaur.tech/".parse().unwrap()
-        // ];
-        // build our application with a route
-        let app = Router::new()
-        .route("/health", axum::routing::get(health_status))
-        .route("/assets", axum::routing::get(assets_folder));
-        
-    
-        // run our app with hyper
-        let listener = tokio::net::TcpListener::bind(&ip_bind).await.map_err(|e| {
-            Error(format!("Couldn't bind to address {ip_bind}: {e}"))
-        });
-
-        match listener {
-            // run our app with hyper
-            Ok(listener) => {
-                let _ = axum::serve(listener, app)
-                    .await;
-            },
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
             Err(e) => {
-                eprintln!("{e:?}");
+                tracing::error!(error = %e, "Couldn't listen for SIGTERM");
+                std::future::pending::<()>().await;
             }
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    });
+    tokio::select! {
+        _ = interrupt => tracing::info!(signal = "SIGINT", "Shutting down"),
+        _ = terminate => tracing::info!(signal = "SIGTERM", "Shutting down"),
+    }
+}
 
-    // build our application with a route
-    let app = Router::new()
-        // `GET /` goes to `root`
-        .route_service("/tetra/hun2.ttf", tower_http::services::ServeFile::new("./assets/tetra/hun2.ttf"))
-        .route_service("/teto/hun2.ttf", tower_http::services::ServeFile::new("./assets/teto/hun2.ttf"))
+/// Serves the cards and the health server until `shutdown` resolves or either of them fails.
+///
+/// Once asked to stop, `/ready` fails and the cards stop taking connections but finish the requests
+/// they are answering, up to [`SHUTDOWN_GRACE`]. Replays reach redis and assets reach the mirror
+/// within those requests, so that is all the flushing the caches need.
+/// The health server goes last, so probes can watch the cards drain.
+async fn serve(state: Arc<AppState>, listener: TcpListener, health_listener: TcpListener, shutdown: impl Future<Output = ()> + Send + 'static) -> anyhow::Result<()> {
+    let (stopping_tx, mut stopping) = watch::channel(false);
+    let (cards_done, health_shutdown) = oneshot::channel::<()>();
+
+    let draining = Arc::clone(&state);
+    let cards = axum::serve(listener, app(Arc::clone(&state))).with_graceful_shutdown(async move {
+        shutdown.await;
+        draining.draining.store(true, Ordering::Relaxed);
+        let _ = stopping_tx.send(true);
+    });
+    let cards = async move {
+        let result = cards.await.map_err(|e| anyhow::anyhow!("The card server failed: {e}"));
+        let _ = cards_done.send(());
+        result
+    };
 
-        .route_service("/teto/unkown_avatar.webp", tower_http::services::ServeFile::new("./assets/teto/unkown_avatar.webp"))
-        .route("/login", get(try_login))
-        .route("/league_recent_test", get(league_recent_test))
-        .route("/league_recent", get(league_recent))
-        .route("/league_replay", get(league_replay))
-        .route("/league_replay_from_data", get(league_replay_from_data))
+    let health = axum::serve(health_listener, health_app(state)).with_graceful_shutdown(async move {
+        let _ = health_shutdown.await;
+    });
+    let health = async move { health.await.map_err(|e| anyhow::anyhow!("The health server failed: {e}")) };
 
-        .route("/teto_test/:user_id", get(teto::teto_test))
-        
-        .with_state(Arc::new(state))
-        ;
-    // run our app with hyper
-    let listener = tokio::net::TcpListener::bind(&ip_bind).await.map_err(|e| {
-        anyhow::anyhow!(Error(format!("Couldn't bind to address {ip_bind}: {e}")))
-    })?;
+    let grace = async move {
+        if stopping.wait_for(|stopping| *stopping).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        tokio::time::sleep(SHUTDOWN_GRACE).await;
+    };
 
-    // run our app with hyper
-    let _ = axum::serve(listener, app)
-        .await;
+    tokio::select! {
+        result = async { tokio::try_join!(cards, health) } => {
+            result?;
+        }
+        _ = grace => tracing::warn!(grace_secs = SHUTDOWN_GRACE.as_secs(), "Gave up on the requests still running"),
+    }
 
     Ok(())
 }
 
 
-async fn health_status() -> impl IntoResponse {
-    "OK"
+/// Files served as they are from the assets directory, by route.
+const STATIC_FILES: &[(&str, &str)] = &[
+    ("/teto/unkown_avatar.webp", "teto/unkown_avatar.webp"),
+];
+
+/// Every file served from disk, by route: the static files and the fonts the templates load.
+fn served_files(assets: &AssetSettings) -> Vec<(String, PathBuf)> {
+    let static_files = STATIC_FILES.iter().map(|&(route, path)| (route.to_string(), assets.dir.join(path)));
+    let fonts = fonts::referenced().into_iter().map(|route| {
+        let path = assets.fonts_dir.join(fonts::file_name(&route));
+        (route, path)
+    });
+
+    static_files.chain(fonts).collect()
 }
 
-async fn try_login(State(state): State<Arc<AppState<'_>>>) -> impl IntoResponse {
-    format!("{:?}", state.tetrio_http_client.me(&state.tetrio_token).await)
-}
-
-fn read_dir_entry(dir_entry: &DirEntry) -> String {
-    match dir_entry.file_type() {
-        Err(_) => String::from("(couldn't read file type)"),
-        Ok(f) => match (f.is_dir(), f.is_file()) {
-            (true, false) => {
-                match std::fs::read_dir(dir_entry.path()) {
-                    Ok(directory) => {
-                        directory.map(|f| {
-                            match f {
-                                Ok(f) => read_dir_entry(&f), 
-                                Err(_) => String::from("(couldn't read file)")
-                            }
-                        }).join("\n")
-            
-                    },
-                    Err(_) => return String::from("Couldn't read directory!"),
-                }
-            },
-            (false, true) => {
-                dir_entry.path().to_string_lossy().to_string()
-            },
-            _ => String::from("Unhandled file type")
-        }
-    }
+/// Probes and metrics, served on their own address so they never queue behind cards.
+fn health_app(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/health", get(health_status))
+        .route("/ready", get(health::ready))
+        .route("/metrics", get(metrics::metrics_page))
+        .route("/assets", get(asset_manifest::asset_manifest))
+        .with_state(state)
 }
 
-async fn assets_folder() -> impl IntoResponse {
-    match std::fs::read_dir("./assets") {
-        Ok(directory) => {
-            directory.map(|f| {
-                match f {
-                    Ok(f) => read_dir_entry(&f), 
-                    Err(_) => String::from("(couldn't read file)")
-                }
-            }).join("\n")
+/// Every page the server hands out, minus the ones turned off in the settings.
+fn app(state: Arc<AppState>) -> Router {
+    let assets = &state.settings.assets.dir;
+    let features = state.settings.features.clone();
+
+    let mut router = Router::new()
+        // `GET /` goes to `root`
+        .route(&format!("{}/*path", asset_mirror::ASSETS_ROUTE), get(asset_mirror::tetrio_asset))
+        .route("/img/avatar/:user_id/:rev", get(image_proxy::avatar))
+        .route("/img/banner/:user_id/:rev", get(image_proxy::banner))
+        .route("/league_recent", get(league_recent))
+        .route("/league_replay", get(league_replay))
+        .route("/league_replay_from_data", get(league_replay_from_data))
+        .route("/league_h2h", get(h2h::league_h2h))
+        .route("/league_session", get(session::league_session))
+
+        .route("/teto_test/:user_id", get(teto::teto_test));
 
-        },
-        Err(_) => return String::from("Couldn't read directory!"),
+    for &(route, path) in STATIC_FILES {
+        router = router.route_service(route, tower_http::services::ServeFile::new(assets.join(path)));
+    }
+
+    for route in fonts::referenced() {
+        router = router.route(&route, get(fonts::font));
+    }
+
+    if features.debug_pages {
+        router = router
+            .route("/login", get(try_login))
+            .route("/league_recent_test", get(league_recent_test));
     }
-}
 
+    if features.png_cards {
+        router = router
+            .route("/league_replay.png", get(league_replay_png))
+            .route("/teto/:file", get(teto::teto_png));
+    }
 
+    if features.json_api {
+        router = router
+            .route("/api/teto/:user_id", get(teto::teto_json))
+            .route("/api/league_recent", get(league_recent_json))
+            .route("/api/league_replay", get(league_replay_json))
+            .route("/api/league_replay_from_data", get(league_replay_from_data_json))
+            .route("/api/league_h2h", get(h2h::league_h2h_json))
+            .route("/api/league_session", get(session::league_session_json));
+    }
+
+    router
+        .route_layer(axum::middleware::from_fn(metrics::track_requests))
+        .with_state(state)
+}
+
+async fn health_status() -> impl IntoResponse {
+    "OK"
+}
 
+async fn try_login(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    format!("{:?}", state.tetrio_http_client.me(&state.settings.tetrio.token).await)
+}
 
   
 
@@ -232,11 +533,11 @@ pub struct GameReplayGameBoard {
 
 impl GameReplayGameBoard {
     pub fn get_id(&self) -> Option<String> {
-        return self.id.clone().or(self.user.clone().map(|user| user.id))
+        self.id.clone().or(self.user.clone().map(|user| user.id))
     }
 
     pub fn get_username(&self) -> Option<String> {
-        return self.username.clone().or(self.user.clone().map(|user| user.username))
+        self.username.clone().or(self.user.clone().map(|user| user.username))
     }
 }
 
@@ -262,6 +563,44 @@ pub struct LeagueEndContextUser {
     pub username: APIstring
 }
 
+impl LeagueEndContextUser {
+    /// Whether `user` (an id or a username) is this player.
+    pub fn is_player(&self, user: &str) -> bool {
+        self.id == user || self.username.eq_ignore_ascii_case(user)
+    }
+}
+
+/// A page of a player's personal league leaderboard.
+///
+/// Read here rather than through `tetrio_api`, so who played each match can be checked before fetching its replay.
+#[derive(Deserialize, Clone, Debug)]
+pub struct LeagueRecords {
+    pub entries: Vec<LeagueRecordEntry>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LeagueRecordEntry {
+    pub replayid: String,
+    /// Whose records these are.
+    pub user: LeagueEndContextUser,
+    /// Everyone else who played the match.
+    #[serde(default)]
+    pub otherusers: Vec<LeagueEndContextUser>,
+    #[serde(default)]
+    pub leaderboard: Vec<LeagueEndContextLeaderboard>,
+}
+
+impl LeagueRecordEntry {
+    pub fn is_duel(&self) -> bool {
+        self.otherusers.len() == 1
+    }
+
+    /// Whether `opponent` (an id or a username) played this match.
+    pub fn is_against(&self, opponent: &str) -> bool {
+        self.otherusers.iter().any(|user| user.is_player(opponent))
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct LeagueEndContext {
    pub leaderboard: Vec<LeagueEndContextLeaderboard>,
@@ -281,6 +620,22 @@ pub struct LeagueEndContextLeaderboard {
     pub stats: LeagueEndContextStats
 }
 
+impl LeagueEndContextLeaderboard {
+    pub fn get_id(&self) -> Option<String> {
+        self.id.clone().or(self.user.clone().map(|user| user.id))
+    }
+
+    pub fn get_username(&self) -> Option<String> {
+        self.username.clone().or(self.user.clone().map(|user| user.username))
+    }
+
+    /// Whether `user` (an id or a username) is this player.
+    pub fn is_player(&self, user: &str) -> bool {
+        self.get_id().as_deref() == Some(user)
+            || self.get_username().is_some_and(|username| username.eq_ignore_ascii_case(user))
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct LeagueEndContextStats {
     pub apm: APIfloat,
@@ -297,6 +652,14 @@ pub struct LeagueEndContextRound {
     pub lifetime: APIint,
 }
 
+impl LeagueEndContextRound {
+    /// Whether `user` (an id or a username) is this player.
+    pub fn is_player(&self, user: &str) -> bool {
+        self.id.as_deref() == Some(user)
+            || self.username.as_deref().is_some_and(|username| username.eq_ignore_ascii_case(user))
+    }
+}
+
 
 
 
@@ -0,0 +1,217 @@
+use std::{collections::BTreeMap, fmt::Write, future::Future, hash::Hash, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, OnceLock}, time::{Duration, Instant}};
+
+use axum::{extract::{MatchedPath, Request}, http::header, middleware::Next, response::{IntoResponse, Response}};
+use moka::future::Cache;
+
+/// Request and render durations, in seconds.
+const DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// A counter per combination of label values.
+struct CounterVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl CounterVec {
+    const fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self { name, help, labels, values: Mutex::new(BTreeMap::new()) }
+    }
+
+    fn inc(&self, values: &[&str]) {
+        let mut counters = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        *counters.entry(values.iter().map(|value| value.to_string()).collect()).or_default() += 1;
+    }
+
+    fn write(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", self.name, self.help, self.name);
+        for (values, count) in self.values.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            let _ = writeln!(out, "{}{} {count}", self.name, label_set(self.labels, values, None));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Not cumulative, the exposition format's running totals are computed when writing.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// A histogram per combination of label values.
+struct HistogramVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, Histogram>>,
+}
+
+impl HistogramVec {
+    const fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self { name, help, labels, values: Mutex::new(BTreeMap::new()) }
+    }
+
+    fn observe(&self, values: &[&str], duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let mut histograms = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        let histogram = histograms.entry(values.iter().map(|value| value.to_string()).collect()).or_default();
+
+        histogram.buckets.resize(DURATION_BUCKETS.len(), 0);
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    fn write(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", self.name, self.help, self.name);
+        for (values, histogram) in self.values.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            let mut cumulative = 0;
+            for (bound, count) in DURATION_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(out, "{}_bucket{} {cumulative}", self.name, label_set(self.labels, values, Some(&bound.to_string())));
+            }
+            let _ = writeln!(out, "{}_bucket{} {}", self.name, label_set(self.labels, values, Some("+Inf")), histogram.count);
+            let _ = writeln!(out, "{}_sum{} {}", self.name, label_set(self.labels, values, None), histogram.sum);
+            let _ = writeln!(out, "{}_count{} {}", self.name, label_set(self.labels, values, None), histogram.count);
+        }
+    }
+}
+
+/// `{name="value",...}`, with an `le` label for histogram buckets.
+fn label_set(names: &[&str], values: &[String], le: Option<&str>) -> String {
+    let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    let mut labels = names.iter()
+        .zip(values)
+        .map(|(name, value)| format!("{name}=\"{}\"", escape(value)))
+        .collect::<Vec<_>>();
+
+    if let Some(le) = le {
+        labels.push(format!("le=\"{le}\""));
+    }
+
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+/// Everything `/metrics` reports, shared by the whole process.
+pub struct Metrics {
+    requests: CounterVec,
+    request_duration: HistogramVec,
+    cache_lookups: CounterVec,
+    upstream_requests: CounterVec,
+    render_duration: HistogramVec,
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+    METRICS.get_or_init(|| Metrics {
+        requests: CounterVec::new("http_requests_total", "Requests answered, by route and status.", &["route", "status"]),
+        request_duration: HistogramVec::new("http_request_duration_seconds", "Time spent answering requests, by route.", &["route"]),
+        cache_lookups: CounterVec::new("cache_lookups_total", "Cache lookups, by cache and whether they were hits.", &["cache", "result"]),
+        upstream_requests: CounterVec::new("upstream_requests_total", "Requests sent to TETR.IO, by client and outcome.", &["client", "outcome"]),
+        render_duration: HistogramVec::new("render_duration_seconds", "Time spent rasterizing cards.", &["kind"]),
+    })
+}
+
+impl Metrics {
+    pub fn record_cache(&self, cache: &str, hit: bool) {
+        self.cache_lookups.inc(&[cache, if hit { "hit" } else { "miss" }]);
+    }
+
+    /// Counts a single attempt; `outcome` is `ok`, `not_found`, `rate_limited`, `server_error`, `timeout`, `circuit_open`...
+    pub fn record_upstream(&self, client: &str, outcome: &str) {
+        self.upstream_requests.inc(&[client, outcome]);
+    }
+
+    pub fn record_render(&self, kind: &str, duration: Duration) {
+        self.render_duration.observe(&[kind], duration);
+    }
+
+    /// Everything recorded so far, in Prometheus' text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.requests.write(&mut out);
+        self.request_duration.write(&mut out);
+        self.cache_lookups.write(&mut out);
+        self.upstream_requests.write(&mut out);
+        self.render_duration.write(&mut out);
+        out
+    }
+}
+
+/// Goes through `cache` like `try_get_with`, counting whether the value was already there.
+///
+/// Callers waiting on another caller's lookup count as hits, they didn't cause a fetch.
+pub async fn try_get_with<K, V, E, F>(name: &str, cache: &Cache<K, V>, key: K, init: F) -> Result<V, Arc<E>>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    E: Send + Sync + 'static,
+    F: Future<Output = Result<V, E>>,
+{
+    let missed = AtomicBool::new(false);
+    let result = cache.try_get_with(key, async {
+        missed.store(true, Ordering::Relaxed);
+        init.await
+    }).await;
+
+    metrics().record_cache(name, !missed.load(Ordering::Relaxed));
+    result
+}
+
+/// Counts and times every request, labelled with the route that matched rather than the raw path.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request.extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| String::from("unmatched"), |path| path.as_str().to_string());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+
+    let metrics = metrics();
+    metrics.requests.inc(&[&route, response.status().as_str()]);
+    metrics.request_duration.observe(&[&route], start.elapsed());
+    response
+}
+
+/// `/metrics`
+pub async fn metrics_page() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics().render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let histogram = HistogramVec::new("test_seconds", "Test.", &["kind"]);
+        histogram.observe(&["a"], Duration::from_millis(20));
+        histogram.observe(&["a"], Duration::from_millis(200));
+        histogram.observe(&["a"], Duration::from_secs(60));
+
+        let mut out = String::new();
+        histogram.write(&mut out);
+
+        assert!(out.contains("test_seconds_bucket{kind=\"a\",le=\"0.01\"} 0\n"), "{out}");
+        assert!(out.contains("test_seconds_bucket{kind=\"a\",le=\"0.025\"} 1\n"), "{out}");
+        assert!(out.contains("test_seconds_bucket{kind=\"a\",le=\"0.25\"} 2\n"), "{out}");
+        assert!(out.contains("test_seconds_bucket{kind=\"a\",le=\"30\"} 2\n"), "{out}");
+        assert!(out.contains("test_seconds_bucket{kind=\"a\",le=\"+Inf\"} 3\n"), "{out}");
+        assert!(out.contains("test_seconds_count{kind=\"a\"} 3\n"), "{out}");
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(label_set(&["route"], &[String::from("a\"b\\c\nd")], None), "{route=\"a\\\"b\\\\c\\nd\"}");
+        assert_eq!(label_set(&[], &[], None), "");
+    }
+}
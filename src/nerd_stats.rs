@@ -0,0 +1,177 @@
+use std::f64::consts::PI;
+
+use serde::Serialize;
+
+/// PPS, APM and VS, along with the metrics the community derives from them.
+///
+/// Formulas follow the usual TETR.IO stats calculators, estimated TR included.
+#[derive(Serialize, Default, Clone, Copy, Debug)]
+pub struct NerdStats {
+    pub pps: f64,
+    pub apm: f64,
+    pub vs: f64,
+    /// Attack per piece.
+    pub app: f64,
+    pub vs_apm: f64,
+    /// Downstack (garbage cleared) per second.
+    pub dss: f64,
+    /// Downstack per piece.
+    pub dsp: f64,
+    pub garbage_efficiency: f64,
+    pub cheese_index: f64,
+    pub area: f64,
+    pub estimated_tr: f64,
+}
+
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0.0 {
+        0.0
+    } else {
+        numerator / denominator
+    }
+}
+
+impl NerdStats {
+    pub fn new(pps: f64, apm: f64, vs: f64) -> Self {
+        let app = ratio(apm, pps * 60.0);
+        let vs_apm = ratio(vs, apm);
+        let dss = vs / 100.0 - apm / 60.0;
+        let dsp = ratio(dss, pps);
+        let garbage_efficiency = ratio(app * dss, pps) * 2.0;
+        let cheese_index = dsp * 150.0 + (vs_apm - 2.0) * 50.0 + (0.6 - app) * 125.0;
+        let area = apm + pps * 45.0 + vs * 0.444 + app * 185.0 + dss * 175.0 + dsp * 450.0 + garbage_efficiency * 315.0;
+
+        Self {
+            pps,
+            apm,
+            vs,
+            app,
+            vs_apm,
+            dss,
+            dsp,
+            garbage_efficiency,
+            cheese_index,
+            area,
+            estimated_tr: estimated_tr(pps, app, dsp, vs_apm),
+        }
+    }
+}
+
+/// TR a player with these stats would sit at, assuming a settled (60) RD.
+fn estimated_tr(pps: f64, app: f64, dsp: f64, vs_apm: f64) -> f64 {
+    if pps == 0.0 {
+        return 0.0;
+    }
+
+    let sr_area = pps * (150.0 + (vs_apm - 1.66) * 35.0) + app * 290.0 + dsp * 700.0;
+    let glicko = 0.000013 * sr_area.powi(3) - 0.0196 * sr_area.powi(2) + 12.645 * sr_area - 1005.4;
+
+    let ln10 = 10f64.ln();
+    let deviation = (3.0 * ln10.powi(2) * 60f64.powi(2) + 2500.0 * (64.0 * PI.powi(2) + 147.0 * ln10.powi(2))).sqrt();
+    25000.0 / (1.0 + 10f64.powf((1500.0 - glicko) * PI / deviation))
+}
+
+#[derive(Serialize, Default, Clone, Copy, Debug)]
+pub struct Spread {
+    pub min: f64,
+    pub max: f64,
+    pub std_dev: f64,
+}
+
+impl Spread {
+    fn new(values: impl Iterator<Item = f64> + Clone) -> Self {
+        let count = values.clone().count();
+        if count == 0 {
+            return Self::default();
+        }
+
+        let mean = values.clone().sum::<f64>() / count as f64;
+        let variance = values.clone().map(|value| (value - mean).powi(2)).sum::<f64>() / count as f64;
+
+        Self {
+            min: values.clone().fold(f64::INFINITY, f64::min),
+            max: values.fold(f64::NEG_INFINITY, f64::max),
+            std_dev: variance.sqrt(),
+        }
+    }
+}
+
+/// The lowest and highest value of each stat over a player's rounds, and its standard deviation.
+#[derive(Serialize, Default, Clone, Copy, Debug)]
+pub struct NerdStatsSpread {
+    pub pps: Spread,
+    pub apm: Spread,
+    pub vs: Spread,
+    pub app: Spread,
+    pub vs_apm: Spread,
+    pub dss: Spread,
+    pub dsp: Spread,
+    pub garbage_efficiency: Spread,
+    pub cheese_index: Spread,
+    pub area: Spread,
+    pub estimated_tr: Spread,
+}
+
+impl NerdStatsSpread {
+    pub fn new(rounds: &[NerdStats]) -> Self {
+        let spread = |metric: fn(&NerdStats) -> f64| Spread::new(rounds.iter().map(metric));
+
+        Self {
+            pps: spread(|round| round.pps),
+            apm: spread(|round| round.apm),
+            vs: spread(|round| round.vs),
+            app: spread(|round| round.app),
+            vs_apm: spread(|round| round.vs_apm),
+            dss: spread(|round| round.dss),
+            dsp: spread(|round| round.dsp),
+            garbage_efficiency: spread(|round| round.garbage_efficiency),
+            cheese_index: spread(|round| round.cheese_index),
+            area: spread(|round| round.area),
+            estimated_tr: spread(|round| round.estimated_tr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(name: &str, actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-3, "{name}: expected {expected}, got {actual}");
+    }
+
+    #[test]
+    fn derived_stats() {
+        // (pps, apm, vs) -> (app, dsp, vs/apm, cheese, garbage efficiency, area), worked out by hand from the calculators' formulas
+        let table = [
+            ((2.0, 60.0, 120.0), (0.5, 0.1, 2.0, 27.5, 0.1, 407.28)),
+            ((1.5, 45.0, 100.0), (0.5, 0.166_667, 2.222_222, 48.611_111, 0.166_667, 420.65)),
+            ((3.0, 180.0, 330.0), (1.0, 0.1, 1.833_333, -43.333_333, 0.2, 807.02)),
+            ((0.0, 0.0, 0.0), (0.0, 0.0, 0.0, -25.0, 0.0, 0.0)),
+        ];
+
+        for ((pps, apm, vs), (app, dsp, vs_apm, cheese_index, garbage_efficiency, area)) in table {
+            let stats = NerdStats::new(pps, apm, vs);
+            let name = |stat: &str| format!("{stat} for {pps} PPS, {apm} APM, {vs} VS");
+
+            assert_close(&name("APP"), stats.app, app);
+            assert_close(&name("DS/piece"), stats.dsp, dsp);
+            assert_close(&name("VS/APM"), stats.vs_apm, vs_apm);
+            assert_close(&name("cheese index"), stats.cheese_index, cheese_index);
+            assert_close(&name("garbage efficiency"), stats.garbage_efficiency, garbage_efficiency);
+            assert_close(&name("area"), stats.area, area);
+        }
+    }
+
+    #[test]
+    fn spread_over_rounds() {
+        let rounds = [NerdStats::new(1.0, 30.0, 60.0), NerdStats::new(3.0, 90.0, 180.0)];
+        let spread = NerdStatsSpread::new(&rounds);
+
+        assert_close("min PPS", spread.pps.min, 1.0);
+        assert_close("max PPS", spread.pps.max, 3.0);
+        assert_close("PPS deviation", spread.pps.std_dev, 1.0);
+        assert_close("APP deviation", spread.app.std_dev, 0.0);
+        assert_close("no rounds", NerdStatsSpread::new(&[]).apm.max, 0.0);
+    }
+}
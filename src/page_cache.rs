@@ -0,0 +1,113 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use axum::{body::Bytes, http::{header, HeaderMap, StatusCode}, response::{IntoResponse, Response}};
+use moka::future::Cache;
+use sha2::{Digest, Sha256};
+use tetrio_api::models::packet::Packet;
+
+use crate::{error::AppError, metrics};
+
+/// How many bytes of rendered pages are kept in memory.
+const PAGE_CACHE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Clients may keep a page but have to ask whether it changed before using it.
+const PAGE_CACHE_CONTROL: &str = "no-cache";
+
+/// Hex digits of a page's SHA-256 kept in its ETag, plenty to tell two versions of a page apart.
+const ETAG_LEN: usize = 32;
+
+/// When TETR.IO produced `packet`, `0` if it didn't say.
+pub fn cached_at<T>(packet: &Packet<T>) -> i64 {
+    packet.cache.as_ref().map_or(0, |cache| cache.cached_at)
+}
+
+/// A rendered page and the ETag it is served with.
+#[derive(Clone)]
+pub struct CachedPage {
+    etag: Arc<str>,
+    content_type: &'static str,
+    body: Bytes,
+}
+
+impl CachedPage {
+    /// The ETag only depends on `body`, so every replica and every build of the server agrees on it.
+    fn new(content_type: &'static str, body: Vec<u8>) -> Self {
+        let digest = format!("{:x}", Sha256::digest(&body));
+
+        Self {
+            etag: format!("\"{}\"", &digest[..ETAG_LEN]).into(),
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    /// Whether the client already has this page, going by its `If-None-Match` header.
+    fn is_fresh(&self, headers: &HeaderMap) -> bool {
+        let Some(tags) = headers.get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok()) else {
+            return false;
+        };
+
+        tags.split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == &*self.etag)
+    }
+
+    /// The page, or an empty `304 Not Modified` when the client's copy is still good.
+    pub fn respond(self, headers: &HeaderMap) -> Response {
+        let validators = [(header::ETAG, self.etag.to_string()), (header::CACHE_CONTROL, PAGE_CACHE_CONTROL.to_owned())];
+
+        if self.is_fresh(headers) {
+            return (StatusCode::NOT_MODIFIED, validators).into_response();
+        }
+
+        (validators, [(header::CONTENT_TYPE, self.content_type)], self.body).into_response()
+    }
+}
+
+/// Rendered cards, keyed by whatever identifies the data they were rendered from.
+///
+/// Callers put the upstream cache timestamps in the key, so a page is rendered again as soon
+/// as TETR.IO has something new.
+pub struct PageCache {
+    pages: Cache<Box<str>, CachedPage>,
+}
+
+impl PageCache {
+    /// Pages say how long ago things happened, so even unchanged data is rendered again after `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            pages: Cache::builder()
+                .max_capacity(PAGE_CACHE_SIZE)
+                .weigher(|_, page: &CachedPage| page.body.len() as u32)
+                .time_to_live(ttl)
+                .build(),
+        }
+    }
+
+    /// Forgets every page, for when the templates changed.
+    pub fn clear(&self) {
+        self.pages.invalidate_all();
+    }
+
+    /// The page cached under `key`, rendering it with `render` if there is none. Failures aren't cached.
+    pub async fn get_or_render<F, Fut>(&self, key: String, content_type: &'static str, render: F) -> Result<CachedPage, AppError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Vec<u8>, AppError>>,
+    {
+        metrics::try_get_with("pages", &self.pages, key.into_boxed_str(), async {
+            render().await.map(|body| CachedPage::new(content_type, body))
+        }).await.map_err(|e| e.as_ref().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn etags_are_stable() {
+        // the SHA-256 of "hello" starts with 2cf24dba5fb0a30e26e83b2ac5b9e29e
+        assert_eq!(&*CachedPage::new("text/html", b"hello".to_vec()).etag, "\"2cf24dba5fb0a30e26e83b2ac5b9e29e\"");
+    }
+}
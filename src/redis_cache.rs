@@ -0,0 +1,180 @@
+use std::{io::{Read, Write}, time::Duration};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use redis::{aio::{ConnectionManager, ConnectionManagerConfig}, AsyncCommands};
+use tokio::sync::OnceCell;
+
+use crate::metrics::metrics;
+
+const KEY_PREFIX: &str = "tetrio_html_server";
+
+/// What a payload is, each kind being kept under keys of its own.
+#[derive(Clone, Copy, Debug)]
+pub enum Kind {
+    /// Raw `/api/games/{id}` payloads, keyed on the replay id.
+    Replay,
+    /// Raw channel API packets, keyed on their url.
+    Packet,
+}
+
+impl Kind {
+    fn key(self, key: &str) -> String {
+        match self {
+            Kind::Replay => format!("{KEY_PREFIX}:replay:{key}"),
+            Kind::Packet => format!("{KEY_PREFIX}:packet:{key}"),
+        }
+    }
+
+    /// The `cache` label of the metrics.
+    fn metric(self) -> &'static str {
+        match self {
+            Kind::Replay => "redis_replays",
+            Kind::Packet => "redis_packets",
+        }
+    }
+}
+
+/// TETR.IO payloads, gzipped and shared in Redis by every replica, so they outlive restarts too.
+///
+/// Redis being down only ever turns into cache misses.
+pub struct RedisCache {
+    client: redis::Client,
+    connection: OnceCell<ConnectionManager>,
+}
+
+fn compress(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn decompress(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+impl RedisCache {
+    pub fn new(client: redis::Client) -> Self {
+        Self { client, connection: OnceCell::new() }
+    }
+
+    async fn connection(&self) -> redis::RedisResult<ConnectionManager> {
+        // a cache shouldn't hold requests up, so give up quickly when redis is unreachable
+        let config = ConnectionManagerConfig::new()
+            .set_number_of_retries(1)
+            .set_connection_timeout(Duration::from_secs(1))
+            .set_response_timeout(Duration::from_secs(1));
+
+        self.connection
+            .get_or_try_init(|| ConnectionManager::new_with_config(self.client.clone(), config))
+            .await
+            .cloned()
+    }
+
+    /// The payload stored for `key`, if any.
+    pub async fn get(&self, kind: Kind, key: &str) -> Option<Vec<u8>> {
+        let result: redis::RedisResult<Option<Vec<u8>>> = async {
+            self.connection().await?.get(kind.key(key)).await
+        }.await;
+
+        metrics().record_cache(kind.metric(), matches!(result, Ok(Some(_))));
+
+        match result {
+            Ok(Some(data)) => match decompress(&data) {
+                Ok(data) => Some(data),
+                Err(e) => {
+                    tracing::warn!(cache = kind.metric(), key, error = %e, "Couldn't decompress a cached payload");
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!(cache = kind.metric(), key, error = %e, "Couldn't read from redis");
+                None
+            }
+        }
+    }
+
+    /// Keeps `payload` for `ttl`, rounded down to the second; nothing is kept for less than a second.
+    pub async fn set(&self, kind: Kind, key: &str, payload: &[u8], ttl: Duration) {
+        if ttl.as_secs() == 0 {
+            return;
+        }
+
+        let data = match compress(payload) {
+            Ok(data) => data,
+            Err(e) => return tracing::warn!(cache = kind.metric(), key, error = %e, "Couldn't compress a payload"),
+        };
+
+        let result: redis::RedisResult<()> = async {
+            self.connection().await?.set_ex(kind.key(key), data, ttl.as_secs()).await
+        }.await;
+
+        if let Err(e) = result {
+            tracing::warn!(cache = kind.metric(), key, error = %e, "Couldn't write to redis");
+        }
+    }
+
+    /// Whether redis answers right now.
+    pub async fn ping(&self) -> redis::RedisResult<()> {
+        let mut connection = self.connection().await?;
+        let _: String = redis::cmd("PING").query_async(&mut connection).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs against the redis `TEST_REDIS_URL` points to, e.g. `redis://127.0.0.1:6379/15`, and is skipped without it.
+    ///
+    /// Only touches keys of its own, which it deletes afterwards.
+    #[tokio::test]
+    async fn redis_round_trip() {
+        let Ok(url) = std::env::var("TEST_REDIS_URL") else {
+            eprintln!("TEST_REDIS_URL isn't set, skipping the redis round trip");
+            return;
+        };
+
+        let client = redis::Client::open(url).expect("Couldn't parse TEST_REDIS_URL");
+        let cache = RedisCache::new(client.clone());
+        cache.ping().await.expect("Redis at TEST_REDIS_URL doesn't answer");
+
+        let mut connection = client.get_multiplexed_async_connection().await.expect("Couldn't connect to redis");
+        let id = format!("test-{}", std::process::id());
+        let corrupt_id = format!("{id}-corrupt");
+        let mut keys = Vec::new();
+
+        let payload = br#"{"success":true,"game":null}"#.repeat(64);
+        for (kind, ttl) in [(Kind::Replay, 600), (Kind::Packet, 60)] {
+            let key = kind.key(&id);
+            cache.set(kind, &id, &payload, Duration::from_secs(ttl)).await;
+            assert_eq!(cache.get(kind, &id).await.as_deref(), Some(&payload[..]), "{kind:?}");
+
+            // stored gzipped, and expiring
+            let stored: Vec<u8> = connection.get(&key).await.expect("Couldn't read the stored payload");
+            assert!(stored.len() < payload.len(), "{} bytes stored for {}", stored.len(), payload.len());
+            assert_eq!(decompress(&stored).expect("Not gzip"), payload);
+            let stored_ttl: i64 = connection.ttl(&key).await.expect("Couldn't read the ttl");
+            assert!((1..=ttl as i64).contains(&stored_ttl), "{kind:?} ttl of {stored_ttl}");
+
+            // a corrupt entry is a miss
+            let corrupt_key = kind.key(&corrupt_id);
+            let _: () = connection.set(&corrupt_key, b"not gzip").await.expect("Couldn't store the corrupt payload");
+            assert_eq!(cache.get(kind, &corrupt_id).await, None, "{kind:?}");
+
+            assert_eq!(cache.get(kind, &format!("{id}-missing")).await, None, "{kind:?}");
+            keys.extend([key, corrupt_key]);
+        }
+
+        // kinds don't share keys, and nothing is kept for less than a second
+        let _: () = connection.del(Kind::Packet.key(&id)).await.expect("Couldn't clean up");
+        assert_eq!(cache.get(Kind::Packet, &id).await, None);
+        cache.set(Kind::Packet, &id, &payload, Duration::from_millis(500)).await;
+        assert_eq!(cache.get(Kind::Packet, &id).await, None);
+
+        let _: () = connection.del(keys).await.expect("Couldn't clean up");
+    }
+}
@@ -0,0 +1,165 @@
+use std::{collections::{HashMap, HashSet}, path::Path, sync::Arc, time::{Duration, Instant}};
+
+use moka::future::Cache;
+use resvg::{tiny_skia, usvg::{self, fontdb, ImageHrefResolver, ImageKind}};
+
+use crate::{asset_mirror::{AssetMirror, ASSETS_ROUTE}, image_proxy::{ImageProxy, UserImage, UNKNOWN_AVATAR, UNKNOWN_AVATAR_PATH}, metrics::metrics};
+
+/// Rasterizes the SVG variants of the cards into PNG images.
+///
+/// Finished images are cached under a key chosen by the caller. TETR.IO's own icons come from the
+/// asset mirror, avatars and banners from the image proxy; cards can't embed anything else.
+pub struct PngRenderer {
+    fontdb: Arc<fontdb::Database>,
+    asset_mirror: Arc<AssetMirror>,
+    image_proxy: Arc<ImageProxy>,
+    rendered_cache: Cache<Box<str>, Arc<Vec<u8>>>,
+}
+
+impl PngRenderer {
+    pub fn new(asset_mirror: Arc<AssetMirror>, image_proxy: Arc<ImageProxy>, fonts_dir: &Path) -> Self {
+        let mut fontdb = fontdb::Database::new();
+        fontdb.load_system_fonts();
+
+        let system_faces = fontdb.faces().map(|face| face.id).collect::<HashSet<_>>();
+        fontdb.load_fonts_dir(fonts_dir);
+        let shipped = fontdb.faces()
+            .find(|face| !system_faces.contains(&face.id))
+            .and_then(|face| face.families.first().map(|(family, _)| family.clone()));
+
+        // the cards ask for `HUN, sans-serif`, and the font's own name may not be HUN;
+        // otherwise `sans-serif` is Arial by default, which may not be installed either
+        let sans_serif = fontdb::Query { families: &[fontdb::Family::SansSerif], ..Default::default() };
+        if let Some(family) = shipped {
+            fontdb.set_sans_serif_family(family);
+        } else if fontdb.query(&sans_serif).is_none() {
+            let fallback = fontdb.faces().find_map(|face| face.families.first().map(|(family, _)| family.clone()));
+            if let Some(family) = fallback {
+                fontdb.set_sans_serif_family(family);
+            }
+        }
+
+        Self {
+            fontdb: Arc::new(fontdb),
+            asset_mirror,
+            image_proxy,
+            rendered_cache: Cache::builder().time_to_live(Duration::from_secs(5 * 60)).build(),
+        }
+    }
+}
+
+/// Undoes the escaping minijinja applies to attribute values.
+fn unescape_attribute(value: &str) -> String {
+    value.replace("&#x2f;", "/")
+        .replace("&#x27;", "'")
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Every `href` an SVG card points to, unescaped.
+fn image_hrefs(svg: &str) -> Vec<String> {
+    let mut hrefs = svg.split("href=\"")
+        .skip(1)
+        .filter_map(|rest| rest.split_once('"'))
+        .map(|(href, _)| unescape_attribute(href))
+        .collect::<Vec<_>>();
+
+    hrefs.sort();
+    hrefs.dedup();
+    hrefs
+}
+
+fn image_kind(data: Arc<Vec<u8>>) -> Option<ImageKind> {
+    match data.as_slice() {
+        [0x89, b'P', b'N', b'G', ..] => Some(ImageKind::PNG(data)),
+        [0xFF, 0xD8, 0xFF, ..] => Some(ImageKind::JPEG(data)),
+        [b'G', b'I', b'F', ..] => Some(ImageKind::GIF(data)),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(ImageKind::WEBP(data)),
+        _ => None,
+    }
+}
+
+impl PngRenderer {
+    async fn fetch_image(&self, href: &str) -> Option<Arc<Vec<u8>>> {
+        if href == UNKNOWN_AVATAR_PATH {
+            return Some(Arc::new(UNKNOWN_AVATAR.to_vec()));
+        }
+
+        if let Some(path) = href.strip_prefix(ASSETS_ROUTE).and_then(|path| path.strip_prefix('/')) {
+            return self.asset_mirror.get(path).await.ok().flatten();
+        }
+
+        if let Some(path) = href.strip_prefix("/img/") {
+            let (kind, rest) = path.split_once('/')?;
+            let (user_id, rev) = rest.split_once('/')?;
+            let kind = match kind {
+                "avatar" => UserImage::Avatar,
+                "banner" => UserImage::Banner,
+                _ => return None,
+            };
+
+            return match self.image_proxy.get(kind, user_id, rev.parse().ok()?).await {
+                Ok(Some(image)) => Some(image),
+                Ok(None) if matches!(kind, UserImage::Avatar) => Some(Arc::new(UNKNOWN_AVATAR.to_vec())),
+                _ => None,
+            };
+        }
+
+        None
+    }
+
+    fn rasterize(svg: &str, fontdb: Arc<fontdb::Database>, images: HashMap<String, Arc<Vec<u8>>>) -> anyhow::Result<Vec<u8>> {
+        let options = usvg::Options {
+            fontdb,
+            image_href_resolver: ImageHrefResolver {
+                // only images fetched ahead of time may be embedded, never files from disk
+                resolve_data: ImageHrefResolver::default_data_resolver(),
+                resolve_string: Box::new(move |href, _| images.get(href).cloned().and_then(image_kind)),
+            },
+            ..Default::default()
+        };
+
+        let tree = usvg::Tree::from_str(svg, &options)?;
+        let size = tree.size().to_int_size();
+        let Some(mut pixmap) = tiny_skia::Pixmap::new(size.width(), size.height()) else {
+            anyhow::bail!("Card has an invalid size ({}x{})", size.width(), size.height());
+        };
+
+        resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+        Ok(pixmap.encode_png()?)
+    }
+
+    /// Forgets every rendered image, for when the templates changed.
+    pub fn clear(&self) {
+        self.rendered_cache.invalidate_all();
+    }
+
+    /// Renders `svg` to a PNG, reusing the image cached under `key` if there is one.
+    pub async fn render(&self, key: &str, svg: String) -> anyhow::Result<Arc<Vec<u8>>> {
+        let cached = self.rendered_cache.get(key).await;
+        metrics().record_cache("png", cached.is_some());
+        if let Some(png) = cached {
+            return Ok(png);
+        }
+
+        let mut images = HashMap::new();
+        for href in image_hrefs(&svg) {
+            if let Some(image) = self.fetch_image(&href).await {
+                images.insert(href, image);
+            }
+        }
+
+        let fontdb = Arc::clone(&self.fontdb);
+        let start = Instant::now();
+        let png = tokio::task::spawn_blocking(move || Self::rasterize(&svg, fontdb, images))
+            .await??;
+        // keys start with the kind of card, e.g. `teto:...`
+        metrics().record_render(key.split(':').next().unwrap_or_default(), start.elapsed());
+
+        let png = Arc::new(png);
+        self.rendered_cache.insert(key.into(), Arc::clone(&png)).await;
+        Ok(png)
+    }
+}
@@ -0,0 +1,233 @@
+use std::sync::Arc;
+
+use axum::{extract::{Query, State}, response::Html, Json};
+use common::LeagueRecord;
+use minijinja::{context, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::{chart::{ChartInput, RoundChart}, error::{AppError, JsonError}, nerd_stats::NerdStats, templates::{self, Contract}, tetra::{self, LeagueReplay}, AppState, LeagueLeaderboard};
+
+fn default_count() -> usize {
+    10
+}
+
+#[derive(Deserialize)]
+pub struct SessionParam {
+    user_id: String,
+    #[serde(default = "default_count")]
+    count: usize,
+}
+
+pub(crate) fn template_contracts() -> Vec<Contract> {
+    vec![
+        Contract::new("tetra/session.html", Value::from_serialize(SessionPage::default())),
+        Contract::new("tetra/partials/session_match.html", context! { session_match => SessionMatch::default() }),
+    ]
+}
+
+#[derive(Serialize, Default, Clone)]
+pub struct SessionMatch {
+    pub replay_id: String,
+    pub opponent: String,
+    pub played_date: String,
+    pub played_time: String,
+    pub score: u32,
+    pub opponent_score: u32,
+    pub won: bool,
+    pub stats: NerdStats,
+    pub opponent_stats: NerdStats,
+}
+
+#[derive(Serialize, Default)]
+pub struct SessionOpponent {
+    pub username: String,
+    pub wins: usize,
+    pub losses: usize,
+    pub rounds_won: u32,
+    pub rounds_lost: u32,
+}
+
+/// The last few league matches of a player.
+#[derive(Serialize, Default)]
+pub struct SessionPage {
+    pub username: String,
+    pub wins: usize,
+    pub losses: usize,
+    /// Length of the streak the player is currently on, `streak_won` telling which kind.
+    pub streak: usize,
+    pub streak_won: bool,
+    pub longest_win_streak: usize,
+    pub opponents: Vec<SessionOpponent>,
+    /// Stats averaged over the session.
+    pub average: NerdStats,
+    /// The player's overall league stats, to compare the session against.
+    pub league_average: Option<NerdStats>,
+    /// Latest match first.
+    pub matches: Vec<SessionMatch>,
+    /// Matches ranked by area, which unlike estimated TR doesn't flatten out at the top.
+    pub best_match: Option<SessionMatch>,
+    pub worst_match: Option<SessionMatch>,
+    pub chart: RoundChart,
+    /// Used by the chart legend.
+    pub left_username: String,
+    pub right_username: String,
+}
+
+impl SessionPage {
+    fn new(records: Vec<(String, LeagueRecord, chrono::DateTime<chrono::Utc>)>, league_average: Option<NerdStats>) -> Self {
+        let username = records.first().map(|(_, record, _)| record.averages.left.username.clone()).unwrap_or_default();
+
+        let matches = records.into_iter().map(|(replay_id, record, timestamp)| {
+            let player = record.averages.left;
+            let opponent = record.averages.right;
+
+            SessionMatch {
+                replay_id,
+                opponent: opponent.username,
+                played_date: timestamp.format("%d/%m/%Y").to_string(),
+                played_time: timestamp.format("%H:%M:%S").to_string(),
+                score: player.score,
+                opponent_score: opponent.score,
+                won: player.score > opponent.score,
+                stats: NerdStats::new(player.pps, player.apm, player.vs),
+                opponent_stats: NerdStats::new(opponent.pps, opponent.apm, opponent.vs),
+            }
+        }).collect::<Vec<_>>();
+
+        let streak_won = matches.first().is_some_and(|session_match| session_match.won);
+        let streak = matches.iter().take_while(|session_match| session_match.won == streak_won).count();
+
+        let longest_win_streak = matches.iter()
+            .fold((0, 0), |(longest, current), session_match| {
+                let current = if session_match.won { current + 1 } else { 0 };
+                (usize::max(longest, current), current)
+            }).0;
+
+        let mut opponents: Vec<SessionOpponent> = Vec::new();
+        for session_match in &matches {
+            let index = match opponents.iter().position(|opponent| opponent.username == session_match.opponent) {
+                Some(index) => index,
+                None => {
+                    opponents.push(SessionOpponent { username: session_match.opponent.clone(), ..Default::default() });
+                    opponents.len() - 1
+                }
+            };
+
+            let opponent = &mut opponents[index];
+            if session_match.won {
+                opponent.wins += 1;
+            } else {
+                opponent.losses += 1;
+            }
+            opponent.rounds_won += session_match.score;
+            opponent.rounds_lost += session_match.opponent_score;
+        }
+
+        let count = matches.len().max(1) as f64;
+        let average = NerdStats::new(
+            matches.iter().map(|session_match| session_match.stats.pps).sum::<f64>() / count,
+            matches.iter().map(|session_match| session_match.stats.apm).sum::<f64>() / count,
+            matches.iter().map(|session_match| session_match.stats.vs).sum::<f64>() / count,
+        );
+
+        let by_area = |a: &&SessionMatch, b: &&SessionMatch| a.stats.area.total_cmp(&b.stats.area);
+        let best_match = matches.iter().max_by(by_area).cloned();
+        let worst_match = matches.iter().min_by(by_area).cloned();
+
+        let chart = RoundChart::new(matches.iter().rev().map(|session_match| ChartInput {
+            label: format!("{} - {}", session_match.opponent, session_match.played_date),
+            weight: 1.0,
+            left: [session_match.stats.pps, session_match.stats.apm, session_match.stats.vs],
+            right: [session_match.opponent_stats.pps, session_match.opponent_stats.apm, session_match.opponent_stats.vs],
+            left_won: session_match.won,
+            right_won: !session_match.won,
+        }).collect());
+
+        Self {
+            left_username: username.clone(),
+            right_username: String::from("opponents"),
+            username,
+            wins: matches.iter().filter(|session_match| session_match.won).count(),
+            losses: matches.iter().filter(|session_match| !session_match.won).count(),
+            streak,
+            streak_won,
+            longest_win_streak,
+            opponents,
+            average,
+            league_average,
+            best_match,
+            worst_match,
+            chart,
+            matches,
+        }
+    }
+}
+
+async fn fetch_session(state: &AppState, params: &SessionParam) -> Result<SessionPage, AppError> {
+    let max_count = state.settings.limits.session_max_count;
+    if !(1..=max_count).contains(&params.count) {
+        return Err(AppError::BadInput(format!("count must be between 1 and {max_count}")));
+    }
+
+    let packet = match state.tetrio_http_client.fetch_user_league_records(&params.user_id, LeagueLeaderboard::Recent, params.count as i64).await {
+        Ok(packet) => packet,
+        Err(e) => return Err(AppError::Upstream(format!("Couldn't fetch recent records: {e:?}")))
+    };
+
+    let Some(data) = &packet.data else {
+        return Err(AppError::UserNotFound(format!("No user named {}", params.user_id)))
+    };
+
+    // multiplayer matches aren't part of a session, no need to fetch them
+    let entries = data.entries.iter().take(params.count).filter(|entry| entry.is_duel()).collect::<Vec<_>>();
+    let Some(first) = entries.first() else {
+        return Err(AppError::NotFound(String::from("No recent records")));
+    };
+
+    // replays only know players by id, `user_id` may be a username
+    let player_id = &first.user.id;
+    let replay_ids = entries.iter().map(|entry| entry.replayid.clone()).collect::<Vec<_>>();
+    let replays = state.tetrio_http_client.fetch_tetrio_replays(&replay_ids, &state.settings.tetrio.token).await;
+
+    let mut records = Vec::new();
+    for (replay_id, replay_data) in replay_ids.into_iter().zip(replays) {
+        let replay_data = match replay_data {
+            Ok(replay_data) => replay_data,
+            Err(e) => return Err(AppError::Upstream(format!("Couldn't fetch replay data: {e}"))),
+        };
+
+        let Some(data) = &replay_data.game else {
+            continue;
+        };
+
+        if let LeagueReplay::Duel(league_record) = tetra::generate_league_replay(data, &replay_id, player_id)? {
+            records.push((replay_id, league_record, data.ts));
+        }
+    }
+
+    if records.is_empty() {
+        return Err(AppError::NotFound(String::from("No recent records")));
+    }
+
+    // the session is still worth showing without the overall stats
+    let league_average = match state.fetch_user_summaries(&params.user_id).await {
+        Ok(packet) => packet.data.as_ref().and_then(|summaries| {
+            let league = &summaries.league;
+            Some(NerdStats::new(league.pps?, league.apm?, league.vs?))
+        }),
+        Err(_) => None,
+    };
+
+    Ok(SessionPage::new(records, league_average))
+}
+
+/// `/league_session`
+pub async fn league_session(State(state): State<Arc<AppState>>, Query(params): Query<SessionParam>) -> Result<Html<String>, AppError> {
+    let page = fetch_session(&state, &params).await?;
+    Ok(Html(templates::render("tetra/session.html", &page)?))
+}
+
+/// `/api/league_session`
+pub async fn league_session_json(State(state): State<Arc<AppState>>, Query(params): Query<SessionParam>) -> Result<Json<SessionPage>, JsonError> {
+    Ok(Json(fetch_session(&state, &params).await?))
+}
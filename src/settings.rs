@@ -0,0 +1,444 @@
+use std::{path::PathBuf, time::Duration};
+
+use serde::Deserialize;
+
+use crate::upstream::{self, TetrioUrls};
+
+/// Read when `SETTINGS_FILE` isn't set; unlike a file named there, it may be missing.
+const DEFAULT_SETTINGS_FILE: &str = "settings.toml";
+
+/// Most records TETR.IO's personal leaderboards hand out at once.
+pub const MAX_RECORDS_LIMIT: usize = 100;
+
+/// Everything the server can be tuned with, see `settings.example.toml`.
+///
+/// Read from a TOML file, then overridden by the environment variables the server always used
+/// (`BIND_URL`, `TETRIO_API_TOKEN`, `REDIS_URL`...).
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub tetrio: TetrioSettings,
+    pub redis: RedisSettings,
+    pub cache: CacheSettings,
+    pub limits: LimitSettings,
+    pub assets: AssetSettings,
+    pub features: FeatureSettings,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    /// Where the cards are served (`BIND_URL`).
+    pub bind: String,
+    /// Where `/health` is served (`HEALTH_URL`).
+    pub health_bind: String,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self { bind: String::from("0.0.0.0:80"), health_bind: String::from("0.0.0.0:8080") }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TetrioSettings {
+    /// The bot's `Bearer ...` token (`TETRIO_API_TOKEN`), needed for replays.
+    pub token: String,
+    /// `TETRIO_API_URL`
+    pub api_url: String,
+    /// `TETRIO_CH_API_URL`
+    pub ch_api_url: String,
+    /// `TETRIO_CDN_URL`
+    pub cdn_url: String,
+    /// How many API requests may be sent each second on average.
+    pub requests_per_second: f64,
+    /// How many API requests may be sent at once after a quiet period.
+    pub burst: f64,
+}
+
+impl Default for TetrioSettings {
+    fn default() -> Self {
+        let urls = TetrioUrls::default();
+        Self {
+            token: String::new(),
+            api_url: urls.api,
+            ch_api_url: urls.ch_api,
+            cdn_url: urls.cdn,
+            requests_per_second: upstream::REQUESTS_PER_SECOND,
+            burst: upstream::BURST,
+        }
+    }
+}
+
+impl TetrioSettings {
+    pub fn urls(&self) -> TetrioUrls {
+        let trim = |url: &str| url.trim_end_matches('/').to_string();
+        TetrioUrls { api: trim(&self.api_url), ch_api: trim(&self.ch_api_url), cdn: trim(&self.cdn_url) }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisSettings {
+    /// `REDIS_URL`
+    pub url: String,
+}
+
+impl Default for RedisSettings {
+    fn default() -> Self {
+        Self { url: String::from("redis://127.0.0.1/") }
+    }
+}
+
+/// How long things are kept, in seconds.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheSettings {
+    /// Replays kept in memory.
+    pub replay_ttl_secs: u64,
+    /// Replays shared in redis; they never change, so they can stay around for a long time.
+    pub replay_redis_ttl_secs: u64,
+    /// Upper bound on how long a user, summaries or records packet is reused, in memory and in redis, whatever TETR.IO says.
+    pub max_packet_ttl_secs: u64,
+    /// Last good answers, served while TETR.IO is failing.
+    pub stale_ttl_secs: u64,
+    /// Rendered cards.
+    pub page_ttl_secs: u64,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            replay_ttl_secs: 15 * 60,
+            replay_redis_ttl_secs: 30 * 24 * 60 * 60,
+            max_packet_ttl_secs: 10 * 60,
+            stale_ttl_secs: 24 * 60 * 60,
+            page_ttl_secs: 10 * 60,
+        }
+    }
+}
+
+impl CacheSettings {
+    pub fn replay_ttl(&self) -> Duration {
+        Duration::from_secs(self.replay_ttl_secs)
+    }
+
+    pub fn replay_redis_ttl(&self) -> Duration {
+        Duration::from_secs(self.replay_redis_ttl_secs)
+    }
+
+    pub fn max_packet_ttl(&self) -> Duration {
+        Duration::from_secs(self.max_packet_ttl_secs)
+    }
+
+    pub fn stale_ttl(&self) -> Duration {
+        Duration::from_secs(self.stale_ttl_secs)
+    }
+
+    pub fn page_ttl(&self) -> Duration {
+        Duration::from_secs(self.page_ttl_secs)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitSettings {
+    /// How many records `/league_h2h` reads from each of the player's personal leaderboards.
+    pub h2h_records: usize,
+    /// Most matches `/league_session` accepts.
+    pub session_max_count: usize,
+}
+
+impl Default for LimitSettings {
+    fn default() -> Self {
+        Self { h2h_records: 50, session_max_count: MAX_RECORDS_LIMIT }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetSettings {
+    /// The fonts and images shipped with the server.
+    pub dir: PathBuf,
+    /// Where TETR.IO's icons and stylesheet are kept (`ASSET_MIRROR_DIR`); may be seeded ahead of time.
+    pub mirror_dir: PathBuf,
+    /// The fonts the templates load (`FONTS_DIR`), looked up by file name.
+    pub fonts_dir: PathBuf,
+}
+
+impl Default for AssetSettings {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("./assets"),
+            mirror_dir: PathBuf::from("./assets/mirror"),
+            fonts_dir: PathBuf::from("./assets/fonts"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeatureSettings {
+    /// `/teto/:user_id.png` and `/league_replay.png`, which are the most expensive routes.
+    pub png_cards: bool,
+    /// The `/api/...` routes.
+    pub json_api: bool,
+    /// `/login` and `/league_recent_test`.
+    pub debug_pages: bool,
+    /// Reads the templates from `assets.dir` and reloads them when they change (`DEV_TEMPLATES`),
+    /// instead of using the ones compiled in.
+    pub dev_templates: bool,
+}
+
+impl Default for FeatureSettings {
+    fn default() -> Self {
+        Self { png_cards: true, json_api: true, debug_pages: true, dev_templates: false }
+    }
+}
+
+/// Whether `bind` looks like `host:port`; the host itself is only resolved when binding.
+fn is_bind_address(bind: &str) -> bool {
+    bind.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+}
+
+/// Whether binding both `a` and `b` would take the same port, a wildcard host taking it on every interface.
+fn same_port(a: &str, b: &str) -> bool {
+    let (Some((a_host, a_port)), Some((b_host, b_port))) = (a.rsplit_once(':'), b.rsplit_once(':')) else {
+        return false;
+    };
+    let wildcard = |host: &str| matches!(host, "0.0.0.0" | "[::]");
+
+    a_port == b_port && (a_host.eq_ignore_ascii_case(b_host) || wildcard(a_host) || wildcard(b_host))
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.strip_prefix("https://").or_else(|| url.strip_prefix("http://")).is_some_and(|host| !host.is_empty())
+}
+
+impl Settings {
+    /// Reads the settings file, applies the environment on top and checks the result.
+    pub fn load() -> anyhow::Result<Self> {
+        let (path, required) = match std::env::var("SETTINGS_FILE") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_SETTINGS_FILE), false),
+        };
+
+        let mut settings = match std::fs::read_to_string(&path) {
+            Ok(text) => Self::from_toml(&text).map_err(|e| anyhow::anyhow!("Couldn't parse {}: {e}", path.display()))?,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => anyhow::bail!("Couldn't read {}: {e}", path.display()),
+        };
+
+        settings.apply_env(|name| std::env::var(name).ok());
+
+        let problems = settings.problems();
+        if !problems.is_empty() {
+            anyhow::bail!("Invalid settings:\n  - {}", problems.join("\n  - "));
+        }
+
+        Ok(settings)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// Lets the environment variables the server has always read win over the file.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) {
+        let overrides: [(&str, &mut String); 7] = [
+            ("BIND_URL", &mut self.server.bind),
+            ("HEALTH_URL", &mut self.server.health_bind),
+            ("TETRIO_API_TOKEN", &mut self.tetrio.token),
+            ("TETRIO_API_URL", &mut self.tetrio.api_url),
+            ("TETRIO_CH_API_URL", &mut self.tetrio.ch_api_url),
+            ("TETRIO_CDN_URL", &mut self.tetrio.cdn_url),
+            ("REDIS_URL", &mut self.redis.url),
+        ];
+
+        for (name, setting) in overrides {
+            if let Some(value) = var(name) {
+                *setting = value;
+            }
+        }
+
+        if let Some(dir) = var("ASSET_MIRROR_DIR") {
+            self.assets.mirror_dir = PathBuf::from(dir);
+        }
+
+        if let Some(dir) = var("FONTS_DIR") {
+            self.assets.fonts_dir = PathBuf::from(dir);
+        }
+
+        if let Some(dev_templates) = var("DEV_TEMPLATES") {
+            self.features.dev_templates = matches!(dev_templates.trim(), "1" | "true" | "yes");
+        }
+    }
+
+    /// Everything wrong with these settings, worded for whoever deploys the server.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for (name, bind) in [("server.bind (BIND_URL)", &self.server.bind), ("server.health_bind (HEALTH_URL)", &self.server.health_bind)] {
+            if !is_bind_address(bind) {
+                problems.push(format!("{name} should look like host:port, got {bind:?}"));
+            }
+        }
+        if same_port(&self.server.bind, &self.server.health_bind) {
+            problems.push(format!("server.bind (BIND_URL) and server.health_bind (HEALTH_URL) both take {}", self.server.health_bind));
+        }
+
+        if self.tetrio.token.trim().is_empty() {
+            problems.push(String::from("tetrio.token (TETRIO_API_TOKEN) is missing"));
+        }
+
+        for (name, url) in [
+            ("tetrio.api_url (TETRIO_API_URL)", &self.tetrio.api_url),
+            ("tetrio.ch_api_url (TETRIO_CH_API_URL)", &self.tetrio.ch_api_url),
+            ("tetrio.cdn_url (TETRIO_CDN_URL)", &self.tetrio.cdn_url),
+        ] {
+            if !is_http_url(url) {
+                problems.push(format!("{name} should be an http(s) url, got {url:?}"));
+            }
+        }
+
+        if !(self.tetrio.requests_per_second.is_finite() && self.tetrio.requests_per_second > 0.0) {
+            problems.push(format!("tetrio.requests_per_second should be positive, got {}", self.tetrio.requests_per_second));
+        }
+        if !(self.tetrio.burst.is_finite() && self.tetrio.burst >= 1.0) {
+            problems.push(format!("tetrio.burst should be at least 1, got {}", self.tetrio.burst));
+        }
+
+        if let Err(e) = redis::Client::open(self.redis.url.as_str()) {
+            problems.push(format!("redis.url (REDIS_URL) is invalid: {e}"));
+        }
+
+        for (name, secs) in [
+            ("cache.replay_ttl_secs", self.cache.replay_ttl_secs),
+            ("cache.replay_redis_ttl_secs", self.cache.replay_redis_ttl_secs),
+            ("cache.max_packet_ttl_secs", self.cache.max_packet_ttl_secs),
+            ("cache.stale_ttl_secs", self.cache.stale_ttl_secs),
+            ("cache.page_ttl_secs", self.cache.page_ttl_secs),
+        ] {
+            if secs == 0 {
+                problems.push(format!("{name} should be at least 1"));
+            }
+        }
+
+        for (name, limit) in [("limits.h2h_records", self.limits.h2h_records), ("limits.session_max_count", self.limits.session_max_count)] {
+            if !(1..=MAX_RECORDS_LIMIT).contains(&limit) {
+                problems.push(format!("{name} should be between 1 and {MAX_RECORDS_LIMIT}, got {limit}"));
+            }
+        }
+
+        if !self.assets.dir.is_dir() {
+            problems.push(format!("assets.dir {} isn't a directory", self.assets.dir.display()));
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid() -> Settings {
+        let mut settings = Settings::default();
+        settings.tetrio.token = String::from("Bearer test");
+        settings.assets.dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+        settings
+    }
+
+    #[test]
+    fn defaults_only_miss_the_token() {
+        assert_eq!(valid().problems(), Vec::<String>::new());
+
+        let mut settings = valid();
+        settings.tetrio.token = String::new();
+        assert_eq!(settings.problems(), vec![String::from("tetrio.token (TETRIO_API_TOKEN) is missing")]);
+    }
+
+    #[test]
+    fn reads_toml() {
+        let settings = Settings::from_toml(r#"
+            [tetrio]
+            api_url = "http://127.0.0.1:8090/api/"
+
+            [cache]
+            page_ttl_secs = 30
+
+            [features]
+            png_cards = false
+        "#).expect("Couldn't parse the settings");
+
+        assert_eq!(settings.tetrio.urls().api, "http://127.0.0.1:8090/api");
+        assert_eq!(settings.tetrio.ch_api_url, "https://ch.tetr.io/api");
+        assert_eq!(settings.cache.page_ttl(), Duration::from_secs(30));
+        assert!(!settings.features.png_cards);
+        assert!(settings.features.json_api);
+    }
+
+    #[test]
+    fn example_is_the_defaults() {
+        let example = Settings::from_toml(include_str!("../settings.example.toml")).expect("Couldn't parse the example");
+        assert_eq!(format!("{example:?}"), format!("{:?}", Settings::default()));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let error = Settings::from_toml("[cache]\npage_ttl = 30\n").expect_err("A typo was accepted");
+        assert!(error.to_string().contains("page_ttl"), "{error}");
+    }
+
+    #[test]
+    fn environment_wins() {
+        let mut settings = Settings::from_toml("[server]\nbind = \"0.0.0.0:3000\"\n").expect("Couldn't parse the settings");
+        settings.apply_env(|name| match name {
+            "BIND_URL" => Some(String::from("127.0.0.1:4000")),
+            "ASSET_MIRROR_DIR" => Some(String::from("/tmp/mirror")),
+            "DEV_TEMPLATES" => Some(String::from("1")),
+            _ => None,
+        });
+
+        assert_eq!(settings.server.bind, "127.0.0.1:4000");
+        assert_eq!(settings.server.health_bind, "0.0.0.0:8080");
+        assert_eq!(settings.assets.mirror_dir, PathBuf::from("/tmp/mirror"));
+        assert!(settings.features.dev_templates);
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let mut settings = valid();
+        settings.server.bind = String::from("80");
+        settings.tetrio.cdn_url = String::from("tetr.io");
+        settings.cache.replay_ttl_secs = 0;
+        settings.limits.h2h_records = 500;
+
+        let problems = settings.problems();
+        assert_eq!(problems.len(), 4, "{problems:#?}");
+        assert!(problems[0].starts_with("server.bind"));
+        assert!(problems[1].starts_with("tetrio.cdn_url"));
+        assert!(problems[2].starts_with("cache.replay_ttl_secs"));
+        assert!(problems[3].starts_with("limits.h2h_records"));
+    }
+
+    #[test]
+    fn servers_need_ports_of_their_own() {
+        for (bind, health_bind, collides) in [
+            ("0.0.0.0:8080", "0.0.0.0:8080", true),
+            ("127.0.0.1:8080", "0.0.0.0:8080", true),
+            ("[::]:9000", "127.0.0.1:9000", true),
+            ("127.0.0.1:8080", "10.0.0.1:8080", false),
+            ("0.0.0.0:80", "0.0.0.0:8080", false),
+        ] {
+            let mut settings = valid();
+            settings.server.bind = String::from(bind);
+            settings.server.health_bind = String::from(health_bind);
+
+            let problems = settings.problems();
+            assert_eq!(problems.iter().any(|problem| problem.contains("both take")), collides, "{bind} and {health_bind}: {problems:#?}");
+        }
+    }
+}
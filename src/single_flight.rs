@@ -0,0 +1,39 @@
+use std::{collections::HashMap, future::Future, sync::{Arc, Mutex}};
+
+use tokio::sync::OnceCell;
+
+/// Makes concurrent callers asking for the same key share one in-flight request.
+///
+/// Nothing is kept once the request is done, caching is left to the caller.
+pub struct SingleFlight<T> {
+    in_flight: Mutex<HashMap<String, Arc<OnceCell<T>>>>,
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self { in_flight: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub async fn run<F, Fut>(&self, key: &str, request: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let cell = {
+            let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+            Arc::clone(in_flight.entry(key.to_string()).or_default())
+        };
+
+        // if the caller running the request goes away, one of the others takes over
+        let result = cell.get_or_init(request).await.clone();
+
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if in_flight.get(key).is_some_and(|current| Arc::ptr_eq(current, &cell)) {
+            in_flight.remove(key);
+        }
+
+        result
+    }
+}
@@ -0,0 +1,299 @@
+use std::{borrow::Cow, path::{Path, PathBuf}, sync::{Arc, RwLock}, time::{Duration, SystemTime}};
+
+use anyhow::bail;
+use minijinja::{AutoEscape, Environment, ErrorKind, HtmlEscape, UndefinedBehavior, Value, value::ValueKind};
+use serde::{Serialize, Serializer};
+
+use crate::fonts;
+
+/// Every template the server knows about, keyed by the name used to render or include it.
+const TEMPLATES: &[(&str, &str)] = &[
+    ("error.html", include_str!("../assets/error.html")),
+    ("teto/index.html", include_str!("../assets/teto/index.html")),
+    ("teto/bot.html", include_str!("../assets/teto/bot.html")),
+    ("teto/banned.html", include_str!("../assets/teto/banned.html")),
+    ("teto/partials/flag.html", include_str!("../assets/teto/partials/flag.html")),
+    ("teto/partials/badge.html", include_str!("../assets/teto/partials/badge.html")),
+    ("teto/partials/distinguishment.html", include_str!("../assets/teto/partials/distinguishment.html")),
+    ("teto/partials/country_ranking.html", include_str!("../assets/teto/partials/country_ranking.html")),
+    ("teto/partials/records_tetra_league.html", include_str!("../assets/teto/partials/records_tetra_league.html")),
+    ("teto/partials/records_tetra_league_rating.html", include_str!("../assets/teto/partials/records_tetra_league_rating.html")),
+    ("teto/partials/records_sprint.html", include_str!("../assets/teto/partials/records_sprint.html")),
+    ("teto/partials/records_blitz.html", include_str!("../assets/teto/partials/records_blitz.html")),
+    ("teto/index.svg", include_str!("../assets/teto/index.svg")),
+    ("teto/bot.svg", include_str!("../assets/teto/bot.svg")),
+    ("teto/banned.svg", include_str!("../assets/teto/banned.svg")),
+    ("tetra/index.html", include_str!("../assets/tetra/index.html")),
+    ("tetra/partials/match.html", include_str!("../assets/tetra/partials/match.html")),
+    ("tetra/partials/extra.html", include_str!("../assets/tetra/partials/extra.html")),
+    ("tetra/partials/chart.html", include_str!("../assets/tetra/partials/chart.html")),
+    ("tetra/partials/session_match.html", include_str!("../assets/tetra/partials/session_match.html")),
+    ("tetra/partials/nerd_stats.html", include_str!("../assets/tetra/partials/nerd_stats.html")),
+    ("tetra/partials/spread.html", include_str!("../assets/tetra/partials/spread.html")),
+    ("tetra/index.svg", include_str!("../assets/tetra/index.svg")),
+    ("tetra/h2h.html", include_str!("../assets/tetra/h2h.html")),
+    ("tetra/session.html", include_str!("../assets/tetra/session.html")),
+    ("tetra/multi.html", include_str!("../assets/tetra/multi.html")),
+    ("tetra/multi.svg", include_str!("../assets/tetra/multi.svg")),
+];
+
+/// How often the template files are checked for changes in dev mode.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+type Sources = Vec<(&'static str, Cow<'static, str>)>;
+
+/// Compiled templates, along with the sources they were compiled from.
+struct Loaded {
+    env: Environment<'static>,
+    sources: Sources,
+}
+
+static LOADED: RwLock<Option<Arc<Loaded>>> = RwLock::new(None);
+
+fn loaded() -> Option<Arc<Loaded>> {
+    LOADED.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn embedded_sources() -> Sources {
+    TEMPLATES.iter().map(|(name, source)| (*name, Cow::Borrowed(*source))).collect()
+}
+
+/// Reads every template from `dir`, laid out like `assets`, instead of using the compiled in copies.
+fn read_sources(dir: &Path) -> anyhow::Result<Sources> {
+    TEMPLATES.iter()
+        .map(|(name, _)| {
+            let path = dir.join(name);
+            match std::fs::read_to_string(&path) {
+                Ok(source) => Ok((*name, Cow::Owned(source))),
+                Err(e) => bail!("Couldn't read template {}: {e}", path.display()),
+            }
+        })
+        .collect()
+}
+
+/// Every template's name and the source currently in use.
+pub fn sources() -> Sources {
+    loaded().map_or_else(embedded_sources, |loaded| loaded.sources.clone())
+}
+
+/// Markup that is inserted into `.html` and `.svg` templates as is.
+///
+/// Everything else a template prints is HTML-escaped, so this should only ever wrap markup the
+/// server wrote itself, with any user-controlled parts run through [`escape_html`] first.
+#[derive(Default, Clone, Debug)]
+pub struct TrustedHtml(String);
+
+impl TrustedHtml {
+    pub fn new(html: String) -> Self {
+        Self(html)
+    }
+}
+
+impl Serialize for TrustedHtml {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Value::from_safe_string(self.0.clone()).serialize(serializer)
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    HtmlEscape(text).to_string()
+}
+
+/// The data a template is rendered with, used to check the template at startup.
+///
+/// `context` should be built from the same types the handlers render with (their `Default`
+/// values are enough), plus whatever loop or `with` variables a partial expects.
+pub struct Contract {
+    pub template: &'static str,
+    pub context: Value,
+}
+
+impl Contract {
+    pub fn new(template: &'static str, context: Value) -> Self {
+        Self { template, context }
+    }
+}
+
+fn build_environment(sources: &Sources) -> anyhow::Result<Environment<'static>> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_auto_escape_callback(|name| {
+        if name.ends_with(".html") || name.ends_with(".svg") {
+            AutoEscape::Html
+        } else {
+            AutoEscape::None
+        }
+    });
+    env.add_global("tetrio_assets", crate::asset_mirror::ASSETS_ROUTE);
+    env.add_filter("fixed", |value: f64, digits: Option<usize>| {
+        format!("{:.*}", digits.unwrap_or(2), value)
+    });
+
+    for (name, source) in sources {
+        env.add_template_owned(*name, source.clone())?;
+    }
+
+    Ok(env)
+}
+
+/// Walks every variable a template reads and makes sure the data it is rendered with has it.
+fn check_contract(env: &Environment<'_>, contract: &Contract) -> anyhow::Result<()> {
+    let template = env.get_template(contract.template)?;
+    let globals = env.globals().map(|(name, _)| name).collect::<Vec<_>>();
+
+    for path in template.undeclared_variables(true) {
+        if globals.contains(&path.as_str()) {
+            continue;
+        }
+
+        let mut current = contract.context.clone();
+        for segment in path.split('.') {
+            if current.kind() != ValueKind::Map {
+                break;
+            }
+
+            let next = current.get_attr(segment)?;
+            if next.is_undefined() {
+                bail!("Template {} uses `{path}` but its data has no `{segment}`", contract.template);
+            }
+            current = next;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compiles `sources` and checks them against the contracts of the pages using them.
+fn compile(sources: Sources) -> anyhow::Result<Loaded> {
+    let env = build_environment(&sources)?;
+
+    let contracts = crate::teto::template_contracts()
+        .into_iter()
+        .chain(crate::tetra::template_contracts())
+        .chain(crate::h2h::template_contracts())
+        .chain(crate::session::template_contracts())
+        .chain(crate::error::template_contracts())
+        .collect::<Vec<_>>();
+
+    for (name, _) in TEMPLATES {
+        if !contracts.iter().any(|contract| contract.template == *name) {
+            bail!("Template {name} has no contract");
+        }
+    }
+
+    for contract in &contracts {
+        check_contract(&env, contract)?;
+    }
+
+    Ok(Loaded { env, sources })
+}
+
+fn install(loaded: Loaded) {
+    *LOADED.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(loaded));
+}
+
+/// Compiles the templates built into the server and checks them.
+///
+/// This or [`init_from_dir`] must be called before anything is rendered.
+pub fn init() -> anyhow::Result<()> {
+    install(compile(embedded_sources())?);
+    Ok(())
+}
+
+/// Like [`init`], reading the templates from `dir` instead, for working on them without a rebuild.
+pub fn init_from_dir(dir: &Path) -> anyhow::Result<()> {
+    install(compile(read_sources(dir)?)?);
+    Ok(())
+}
+
+async fn modified_times(dir: &Path) -> Vec<Option<SystemTime>> {
+    let mut times = Vec::with_capacity(TEMPLATES.len());
+    for (name, _) in TEMPLATES {
+        times.push(tokio::fs::metadata(dir.join(name)).await.and_then(|metadata| metadata.modified()).ok());
+    }
+    times
+}
+
+/// Reloads the templates from `dir` whenever one of them changes, then calls `on_reload`.
+///
+/// A template that doesn't compile or breaks its contract is reported, and the previous ones are kept.
+pub fn watch(dir: PathBuf, on_reload: impl Fn() + Send + 'static) {
+    tokio::spawn(async move {
+        let mut last = modified_times(&dir).await;
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            let current = modified_times(&dir).await;
+            if current == last {
+                continue;
+            }
+            last = current;
+
+            let reload_dir = dir.clone();
+            match tokio::task::spawn_blocking(move || init_from_dir(&reload_dir)).await {
+                Ok(Ok(())) => {
+                    tracing::info!(dir = %dir.display(), "Reloaded templates");
+                    on_reload();
+                }
+                Ok(Err(e)) => tracing::warn!(dir = %dir.display(), error = format!("{e:#}"), "Kept the previous templates"),
+                Err(e) => tracing::error!(dir = %dir.display(), error = %e, "Couldn't reload templates"),
+            }
+        }
+    });
+}
+
+pub fn render<S: Serialize>(name: &str, context: &S) -> Result<String, minijinja::Error> {
+    let Some(loaded) = loaded() else {
+        return Err(minijinja::Error::new(ErrorKind::InvalidOperation, "templates were not initialized"));
+    };
+
+    let page = loaded.env.get_template(name)?.render(context)?;
+    Ok(if name.ends_with(".html") { fonts::subset_font_urls(page) } else { page })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A copy of the templates that can be edited.
+    fn copy_templates(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tetrio_html_server_templates_{name}_{}", std::process::id()));
+        for (name, source) in TEMPLATES {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn reads_templates_from_disk() {
+        let dir = copy_templates("edited");
+        let bot = dir.join("teto/bot.html");
+        let source = std::fs::read_to_string(&bot).unwrap();
+        std::fs::write(&bot, source.replace("</body>", "<!-- edited --></body>")).unwrap();
+
+        let loaded = compile(read_sources(&dir).unwrap()).expect("Edited templates should compile");
+        let (_, source) = loaded.sources.iter().find(|(name, _)| *name == "teto/bot.html").unwrap();
+        assert!(source.contains("<!-- edited -->"));
+        assert!(loaded.env.get_template("teto/bot.html").unwrap().source().contains("<!-- edited -->"));
+    }
+
+    #[test]
+    fn rejects_broken_templates() {
+        let dir = copy_templates("broken");
+        std::fs::write(dir.join("teto/bot.html"), "{{ no_such_field }}").unwrap();
+
+        let Err(error) = compile(read_sources(&dir).unwrap()) else {
+            panic!("A broken contract was accepted");
+        };
+        assert!(error.to_string().contains("no_such_field"), "{error}");
+    }
+
+    #[test]
+    fn reports_missing_templates() {
+        let error = read_sources(Path::new("/nonexistent")).expect_err("Missing templates were accepted");
+        assert!(error.to_string().contains("/nonexistent/error.html"), "{error}");
+    }
+}
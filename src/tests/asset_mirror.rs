@@ -0,0 +1,38 @@
+use axum::http::StatusCode;
+
+use super::get;
+
+#[tokio::test]
+async fn mirrors_images() {
+    let response = get("/tetrio/res/flags/fr.png").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.content_type, "image/png");
+
+    // served from disk from now on
+    let again = get("/tetrio/res/flags/fr.png").await;
+    assert_eq!(again.body, response.body);
+}
+
+#[tokio::test]
+async fn points_stylesheets_at_the_mirror() {
+    let response = get("/tetrio/css/tetrio.css").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.content_type, "text/css");
+    assert!(response.body.contains("url(/tetrio/res/font/hun2.ttf)"), "{}", response.body);
+    assert!(response.body.contains("url('/tetrio/res/bg/1.jpg')"), "{}", response.body);
+    assert!(response.body.contains("url(\"//tetr.io/res/flags/fr.png\")"), "{}", response.body);
+}
+
+#[tokio::test]
+async fn missing_files() {
+    assert_eq!(get("/tetrio/res/flags/zz.png").await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn only_mirrors_static_files() {
+    assert_eq!(get("/tetrio/api/users/taka").await.status, StatusCode::NOT_FOUND);
+    assert_eq!(get("/tetrio/res/../api/users/taka").await.status, StatusCode::NOT_FOUND);
+    assert_eq!(get("/tetrio/res/%2e%2e/%2e%2e/Cargo.toml").await.status, StatusCode::NOT_FOUND);
+}
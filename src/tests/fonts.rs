@@ -0,0 +1,53 @@
+use axum::{body::Body, http::{Request, StatusCode}};
+
+use super::{app_with, get, send, settings, TEST_FONT};
+
+/// Whether `font` still has an outline for `c`, subsets keep the glyph but may empty it.
+fn draws(font: &[u8], c: char) -> bool {
+    let face = ttf_parser::Face::parse(font, 0).expect("Not a font");
+    let glyph = face.glyph_index(c).unwrap_or_else(|| panic!("The test font has no {c}"));
+    face.glyph_bounding_box(glyph).is_some()
+}
+
+#[tokio::test]
+async fn serves_whole_fonts() {
+    let response = get("/teto/hun2.ttf").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.content_type, "font/ttf");
+    assert_eq!(response.bytes, TEST_FONT);
+}
+
+#[tokio::test]
+async fn subsets_to_the_requested_text() {
+    let response = get("/tetra/hun2.ttf?text=%C3%A9").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.bytes.len() < TEST_FONT.len() / 2, "{} bytes out of {}", response.bytes.len(), TEST_FONT.len());
+    assert!(draws(&response.bytes, 'A'));
+    assert!(draws(&response.bytes, 'é'));
+    assert!(!draws(&response.bytes, 'ë'));
+}
+
+#[tokio::test]
+async fn pages_load_subsets() {
+    let response = get("/teto_test/taka").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.contains("url('/teto/hun2.ttf?text="), "{}", response.body);
+}
+
+#[tokio::test]
+async fn subsets_are_bounded() {
+    let uri = format!("/teto/hun2.ttf?text={}", "%C3%A9".repeat(5000));
+    assert_eq!(get(&uri).await.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn missing_fonts() {
+    let mut settings = settings();
+    settings.assets.fonts_dir = std::env::temp_dir().join(format!("tetrio_html_server_no_fonts_{}", std::process::id()));
+
+    let response = send(app_with(settings), Request::get("/teto/hun2.ttf").body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
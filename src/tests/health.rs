@@ -0,0 +1,92 @@
+use std::sync::atomic::Ordering;
+
+use axum::{body::Body, http::{Request, StatusCode}};
+
+use super::{get, health_app, send, settings, state, TEST_FONT};
+
+fn request(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).expect("Couldn't build the request")
+}
+
+#[tokio::test]
+async fn metrics_count_requests_caches_and_upstream_calls() {
+    assert_eq!(get("/teto_test/osk").await.status, StatusCode::OK);
+
+    let response = send(health_app(), request("/metrics")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.content_type.starts_with("text/plain"));
+
+    for line in [
+        "# TYPE http_requests_total counter",
+        "http_requests_total{route=\"/teto_test/:user_id\",status=\"200\"}",
+        "http_request_duration_seconds_bucket{route=\"/teto_test/:user_id\",le=\"+Inf\"}",
+        "cache_lookups_total{cache=\"user_info\",result=\"miss\"}",
+        "cache_lookups_total{cache=\"pages\",result=\"miss\"}",
+        "upstream_requests_total{client=\"api\",outcome=\"ok\"}",
+    ] {
+        assert!(response.body.contains(line), "{line} is missing from\n{}", response.body);
+    }
+}
+
+#[tokio::test]
+async fn not_ready_without_redis() {
+    let response = send(health_app(), request("/ready")).await;
+
+    // the tests' redis url points at a closed port, while the mock TETR.IO accepts the token
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    let readiness: serde_json::Value = serde_json::from_str(&response.body).expect("Not JSON");
+    assert_eq!(readiness["redis"]["ok"], false, "{readiness}");
+    assert!(readiness["redis"]["error"].as_str().is_some_and(|error| error.contains("redis")), "{readiness}");
+    assert_eq!(readiness["tetrio"], serde_json::json!({ "ok": true }));
+    assert_eq!(readiness["serving"], serde_json::json!({ "ok": true }));
+}
+
+#[tokio::test]
+async fn not_ready_while_draining() {
+    let state = state(settings());
+    state.draining.store(true, Ordering::Relaxed);
+
+    let response = send(crate::health_app(state), request("/ready")).await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    let readiness: serde_json::Value = serde_json::from_str(&response.body).expect("Not JSON");
+    assert_eq!(readiness["serving"], serde_json::json!({ "ok": false, "error": "Shutting down" }));
+}
+
+#[tokio::test]
+async fn still_alive() {
+    let response = send(health_app(), request("/health")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body, "OK");
+}
+
+#[tokio::test]
+async fn asset_manifest_lists_what_shipped() {
+    let response = send(health_app(), request("/assets")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.content_type.starts_with("application/json"));
+
+    let manifest: serde_json::Value = serde_json::from_str(&response.body).expect("Not JSON");
+    assert_eq!(manifest["version"], env!("CARGO_PKG_VERSION"));
+
+    let index = manifest["templates"].as_array().unwrap().iter()
+        .find(|template| template["name"] == "teto/index.html")
+        .expect("teto/index.html isn't listed");
+    let source = include_str!("../../assets/teto/index.html");
+    assert_eq!(index["size"], source.len());
+    assert_eq!(index["sha256"].as_str().map(str::len), Some(64));
+
+    let avatar = manifest["files"].as_array().unwrap().iter()
+        .find(|file| file["route"] == "/teto/unkown_avatar.webp")
+        .expect("The unknown avatar isn't listed");
+    assert_eq!(avatar["size"], crate::image_proxy::UNKNOWN_AVATAR.len());
+    assert!(avatar["modified"].is_string());
+
+    let font = manifest["files"].as_array().unwrap().iter()
+        .find(|file| file["route"] == "/teto/hun2.ttf")
+        .expect("The font isn't listed");
+    assert_eq!(font["size"], TEST_FONT.len());
+    assert_eq!(manifest["missing"], serde_json::json!([]));
+
+    // routes only, never where the files live
+    assert!(!response.body.contains(env!("CARGO_MANIFEST_DIR")), "{}", response.body);
+}
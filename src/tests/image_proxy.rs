@@ -0,0 +1,42 @@
+use axum::http::StatusCode;
+use image::GenericImageView;
+
+use super::get;
+use crate::image_proxy::UNKNOWN_AVATAR;
+
+#[tokio::test]
+async fn resizes_avatars() {
+    let response = get("/img/avatar/5e0000000000000000000001/1690000000000").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.content_type, "image/webp");
+
+    let image = image::load_from_memory_with_format(&response.bytes, image::ImageFormat::WebP).expect("Not a WebP image");
+    assert_eq!(image.dimensions(), (280, 280));
+}
+
+#[tokio::test]
+async fn resizes_banners() {
+    let response = get("/img/banner/5e0000000000000000000003/1690000000000").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+
+    let image = image::load_from_memory_with_format(&response.bytes, image::ImageFormat::WebP).expect("Not a WebP image");
+    assert_eq!(image.dimensions(), (1800, 360));
+}
+
+#[tokio::test]
+async fn missing_avatars_fall_back() {
+    for uri in ["/img/avatar/5e0000000000000000000002/1690000000000", "/img/avatar/..%2F..%2Fapi/1"] {
+        let response = get(uri).await;
+
+        assert_eq!(response.status, StatusCode::OK, "{uri}");
+        assert_eq!(response.bytes, UNKNOWN_AVATAR, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn missing_banners() {
+    assert_eq!(get("/img/banner/5e0000000000000000000002/1690000000000").await.status, StatusCode::NOT_FOUND);
+    assert_eq!(get("/img/banner/5e0000000000000000000003/latest").await.status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use tokio::{net::TcpListener, sync::oneshot};
+
+use super::{settings, state};
+
+async fn listener() -> TcpListener {
+    TcpListener::bind("127.0.0.1:0").await.expect("Couldn't bind a test listener")
+}
+
+#[tokio::test]
+async fn stops_both_servers_on_shutdown() {
+    let (cards, health) = (listener().await, listener().await);
+    let (cards_addr, health_addr) = (cards.local_addr().unwrap(), health.local_addr().unwrap());
+    let (stop, stopped) = oneshot::channel::<()>();
+
+    let server = tokio::spawn(crate::serve(state(settings()), cards, health, async move {
+        let _ = stopped.await;
+    }));
+
+    let client = reqwest::Client::new();
+    let page = client.get(format!("http://{cards_addr}/teto_test/taka")).send().await.expect("The cards aren't served");
+    assert!(page.status().is_success());
+    let health = client.get(format!("http://{health_addr}/health")).send().await.expect("The health server isn't up");
+    assert_eq!(health.text().await.unwrap(), "OK");
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), server)
+        .await
+        .expect("The servers didn't stop")
+        .expect("The servers panicked")
+        .expect("The servers failed");
+
+    assert!(tokio::net::TcpStream::connect(cards_addr).await.is_err(), "The cards are still served");
+    assert!(tokio::net::TcpStream::connect(health_addr).await.is_err(), "The health server is still up");
+}
+
+#[tokio::test]
+async fn busy_addresses_are_errors() {
+    let taken = listener().await;
+    let address = taken.local_addr().unwrap().to_string();
+
+    let Err(error) = crate::bind(&address).await else {
+        panic!("{address} was bound twice");
+    };
+    assert!(error.to_string().contains(&address), "{error}");
+}
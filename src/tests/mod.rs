@@ -0,0 +1,169 @@
+//! End to end tests: the server's router, reading from the mock TETR.IO in `src/bin/mock_tetrio.rs`.
+//!
+//! Pages are compared against the snapshots in `src/tests/snapshots`; after an intended change to a
+//! page, review and accept the new ones with `cargo insta review`.
+
+mod asset_mirror;
+mod fonts;
+mod health;
+mod image_proxy;
+mod lifecycle;
+mod page_cache;
+mod settings;
+mod teto;
+mod tetra;
+mod upstream;
+
+use std::{net::SocketAddr, path::PathBuf, sync::{Arc, OnceLock}};
+
+use axum::{body::Body, http::{header, Request, StatusCode}, response::Response, Router};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+use crate::{settings::Settings, templates, upstream::TetrioUrls, AppState};
+
+// only `router` is used here, `main` belongs to the binary
+#[allow(dead_code)]
+#[path = "../bin/mock_tetrio.rs"]
+mod mock_tetrio;
+
+/// Starts the mock TETR.IO once, and points the server at it.
+///
+/// The mock gets a runtime of its own, so it outlives the test that happened to start it.
+fn mock_tetrio() -> SocketAddr {
+    static MOCK_TETRIO: OnceLock<SocketAddr> = OnceLock::new();
+
+    *MOCK_TETRIO.get_or_init(|| {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Couldn't bind the mock TETR.IO");
+        listener.set_nonblocking(true).expect("Couldn't make the mock TETR.IO non blocking");
+        let addr = listener.local_addr().expect("The mock TETR.IO has no address");
+        let router = mock_tetrio::router(PathBuf::from(mock_tetrio::DEFAULT_FIXTURES)).expect("Couldn't load the fixtures");
+
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("Couldn't start the mock TETR.IO runtime");
+            runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener).expect("Couldn't hand the listener to tokio");
+                axum::serve(listener, router).await.expect("The mock TETR.IO stopped");
+            });
+        });
+
+        crate::upstream::init_urls(TetrioUrls {
+            api: format!("http://{addr}/api"),
+            ch_api: format!("http://{addr}/api"),
+            cdn: format!("http://{addr}"),
+        });
+        assert_eq!(crate::upstream::urls().cdn, format!("http://{addr}"), "TETR.IO urls were read before the mock started");
+        templates::init().expect("Couldn't load the templates");
+
+        addr
+    })
+}
+
+/// Shared by every test, files only ever get added to it.
+fn asset_mirror_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("tetrio_html_server_assets_{}", std::process::id()))
+}
+
+/// Stands in for hun2.ttf in the tests: Tuffy, which is in the public domain.
+pub const TEST_FONT: &[u8] = include_bytes!("../../tests/fixtures/fonts/Tuffy.ttf");
+
+/// Shared by every test, with the test font installed under the name of every font the templates load.
+fn fonts_dir() -> PathBuf {
+    static FONTS_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+    FONTS_DIR.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("tetrio_html_server_fonts_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Couldn't create the fonts directory");
+        for file in crate::fonts::missing(&dir) {
+            std::fs::write(dir.join(file), TEST_FONT).expect("Couldn't install the test font");
+        }
+        dir
+    }).clone()
+}
+
+/// The defaults, with the checkout's assets and a token the mock doesn't check.
+pub fn settings() -> Settings {
+    let mut settings = Settings::default();
+    settings.tetrio.token = String::from("Bearer test");
+    settings.assets.dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    settings.assets.mirror_dir = asset_mirror_dir();
+    settings.assets.fonts_dir = fonts_dir();
+    settings
+}
+
+/// A fresh server, so caches never leak from one test to another.
+fn app() -> Router {
+    app_with(settings())
+}
+
+fn app_with(settings: Settings) -> Router {
+    crate::app(state(settings))
+}
+
+/// The side server, with its own fresh state.
+fn health_app() -> Router {
+    crate::health_app(state(settings()))
+}
+
+fn state(settings: Settings) -> Arc<AppState> {
+    mock_tetrio();
+
+    // nothing listens there, so every redis lookup is a quick miss
+    let redis = redis::Client::open("redis://127.0.0.1:1/").expect("Couldn't parse the redis url");
+    Arc::new(AppState::new(settings, redis))
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub content_type: String,
+    pub etag: Option<String>,
+    pub body: String,
+    pub bytes: Vec<u8>,
+}
+
+fn header_value(response: &Response, name: header::HeaderName) -> Option<String> {
+    response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+/// Sends `request` to `app`, which may be reused to share its caches between requests.
+pub async fn send(app: Router, request: Request<Body>) -> TestResponse {
+    let response = app.oneshot(request).await.expect("The router failed");
+
+    let status = response.status();
+    let content_type = header_value(&response, header::CONTENT_TYPE).unwrap_or_default();
+    let etag = header_value(&response, header::ETAG);
+    let body = response.into_body().collect().await.expect("Couldn't read the body").to_bytes();
+
+    TestResponse { status, content_type, etag, body: String::from_utf8_lossy(&body).into_owned(), bytes: body.to_vec() }
+}
+
+pub async fn get(uri: &str) -> TestResponse {
+    send(app(), Request::get(uri).body(Body::empty()).expect("Couldn't build the request")).await
+}
+
+/// Fetches `uri`, checks its status and compares the page against the `name` snapshot.
+///
+/// The mock's address and "how long ago" texts change from run to run, so they are masked.
+pub async fn assert_page(name: &str, uri: &str, status: StatusCode) {
+    let response = get(uri).await;
+    assert_eq!(response.status, status, "{uri} answered {}", response.body);
+    assert!(response.content_type.starts_with("text/html"), "{uri} answered {}", response.content_type);
+
+    insta::with_settings!({
+        filters => vec![
+            (r"http(:|&#x3a;)(/|&#x2f;)(/|&#x2f;)127\.0\.0\.1:\d+", "[tetrio]"),
+            (r"\b\d+ (YEAR|MONTH|WEEK|DAY|HOUR|MINUTE|SECOND)S?\b", "[ago]"),
+        ],
+        prepend_module_to_snapshot => false,
+    }, {
+        insta::assert_snapshot!(name, response.body);
+    });
+}
+
+/// Checks `page` shows the markup of the `hostile` fixtures as text, and never as tags.
+pub fn assert_escaped(page: &str) {
+    let lowercase = page.to_lowercase();
+    assert!(!lowercase.contains("<script"), "A script tag got through:\n{page}");
+    assert!(!lowercase.contains("<img src=x"), "An image tag got through:\n{page}");
+    assert!(lowercase.contains("&lt;script&gt;"), "The hostile markup is missing:\n{page}");
+}
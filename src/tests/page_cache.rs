@@ -0,0 +1,63 @@
+use axum::{body::Body, http::{header, Request, StatusCode}};
+
+use super::{app, get, send};
+
+fn revalidate(uri: &str, if_none_match: &str) -> Request<Body> {
+    Request::get(uri)
+        .header(header::IF_NONE_MATCH, if_none_match)
+        .body(Body::empty())
+        .expect("Couldn't build the request")
+}
+
+#[tokio::test]
+async fn revalidates_unchanged_pages() {
+    for uri in ["/teto_test/taka", "/api/teto/taka", "/teto/taka.png"] {
+        let app = app();
+        let page = send(app.clone(), Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(page.status, StatusCode::OK, "{uri} answered {}", page.body);
+        let etag = page.etag.unwrap_or_else(|| panic!("{uri} has no ETag"));
+
+        let revalidated = send(app, revalidate(uri, &etag)).await;
+        assert_eq!(revalidated.status, StatusCode::NOT_MODIFIED, "{uri}");
+        assert_eq!(revalidated.etag.as_ref(), Some(&etag), "{uri}");
+        assert!(revalidated.bytes.is_empty(), "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn etags_survive_restarts() {
+    // the tag comes from the page itself, so a fresh server agrees with the one that handed it out
+    let etag = get("/teto_test/kagari").await.etag.expect("No ETag");
+    let revalidated = send(app(), revalidate("/teto_test/kagari", &format!("\"0000000000000000\", W/{etag}"))).await;
+
+    assert_eq!(revalidated.status, StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn changed_pages_are_sent_again() {
+    let page = send(app(), revalidate("/teto_test/taka", "\"0000000000000000\"")).await;
+
+    assert_eq!(page.status, StatusCode::OK);
+    assert!(page.content_type.starts_with("text/html"));
+    assert!(page.body.contains("TAKA"));
+}
+
+#[tokio::test]
+async fn variants_have_their_own_etags() {
+    let html = get("/teto_test/taka").await.etag;
+    let json = get("/api/teto/taka").await.etag;
+    let png = get("/teto/taka.png").await.etag;
+
+    assert!(html.is_some() && json.is_some() && png.is_some());
+    assert_ne!(html, json);
+    assert_ne!(html, png);
+    assert_ne!(json, png);
+}
+
+#[tokio::test]
+async fn errors_are_not_tagged() {
+    let response = get("/teto_test/nobody").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.etag, None);
+}
@@ -0,0 +1,20 @@
+use axum::{body::Body, http::{Request, StatusCode}};
+
+use super::{app_with, send, settings};
+
+#[tokio::test]
+async fn features_can_be_turned_off() {
+    let mut settings = settings();
+    settings.features.png_cards = false;
+    settings.features.json_api = false;
+    settings.features.debug_pages = false;
+    let app = app_with(settings);
+
+    for uri in ["/teto/taka.png", "/api/teto/taka", "/league_recent_test?user_id=taka"] {
+        let response = send(app.clone(), Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND, "{uri}");
+    }
+
+    let response = send(app, Request::get("/teto_test/taka").body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status, StatusCode::OK);
+}
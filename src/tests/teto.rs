@@ -0,0 +1,105 @@
+use axum::http::StatusCode;
+
+use super::{assert_escaped, assert_page, get};
+
+#[tokio::test]
+async fn normal_user() {
+    assert_page("teto_normal", "/teto_test/taka", StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn user_by_id() {
+    let by_name = get("/teto_test/taka").await;
+    let by_id = get("/teto_test/5e0000000000000000000001").await;
+
+    assert_eq!(by_name.body, by_id.body);
+}
+
+#[tokio::test]
+async fn staff_user() {
+    assert_page("teto_staff", "/teto_test/osk", StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn banned_user() {
+    assert_page("teto_banned", "/teto_test/cheater", StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn bot_user() {
+    assert_page("teto_bot", "/teto_test/tetrabot", StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn supporter_user() {
+    assert_page("teto_supporter", "/teto_test/kagari", StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn champion_user() {
+    assert_page("teto_champion", "/teto_test/czsmall", StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn twc_user() {
+    assert_page("teto_twc", "/teto_test/vincehd", StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn bad_standing_user() {
+    assert_page("teto_bad_standing", "/teto_test/grief", StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn unknown_user() {
+    assert_page("teto_unknown", "/teto_test/nobody", StatusCode::NOT_FOUND).await;
+}
+
+#[tokio::test]
+async fn profile_card() {
+    let response = get("/teto/taka.png").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.content_type, "image/png");
+}
+
+/// Every field TETR.IO lets a user fill in, filled with markup.
+#[tokio::test]
+async fn hostile_user() {
+    let response = get("/teto_test/hostile").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_escaped(&response.body);
+
+    for escaped in [
+        "&lt;SCRIPT&gt;ALERT(&quot;X&quot;)&lt;&#x2f;SCRIPT&gt;&#x27;",
+        // the country and the badge end up in attributes
+        "flags&#x2f;&quot;&gt;&lt;script&gt;alert(1)&lt;&#x2f;script&gt;.png",
+        "badges&#x2f;&quot;&gt;&lt;script&gt;alert(2)&lt;&#x2f;script&gt;.png",
+    ] {
+        assert!(response.body.contains(escaped), "{escaped} is missing from\n{}", response.body);
+    }
+}
+
+/// The logos are the only markup a distinguishment keeps, whatever surrounds them.
+#[tokio::test]
+async fn hostile_distinguishment() {
+    let response = get("/teto_test/hostile").await;
+
+    for html in [
+        r#"&lt;script&gt;alert(3)&lt;&#x2f;script&gt; <img src="/tetrio/res/osk.svg"> &quot;TETR.IO&quot; &#x27;FOUNDER&#x27;"#,
+        r#"made <img src="/tetrio/res/tetrio-logo.svg" style="filter: invert(1);"> &lt;img src=x onerror=alert(4)&gt;"#,
+    ] {
+        assert!(response.body.contains(html), "{html} is missing from\n{}", response.body);
+    }
+}
+
+#[tokio::test]
+async fn hostile_bot() {
+    let response = get("/teto_test/hostilebot").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_escaped(&response.body);
+
+    for escaped in ["&lt;B&gt;BOT&lt;&#x2f;B&gt;", "&lt;SCRIPT&gt;ALERT(5)&lt;&#x2f;SCRIPT&gt;&quot;&#x27;"] {
+        assert!(response.body.contains(escaped), "{escaped} is missing from\n{}", response.body);
+    }
+}
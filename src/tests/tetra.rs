@@ -0,0 +1,140 @@
+use axum::http::StatusCode;
+
+use super::{assert_escaped, assert_page, get};
+
+const TAKA: &str = "5e0000000000000000000001";
+const VINCEHD: &str = "5e0000000000000000000007";
+
+#[tokio::test]
+async fn won_replay() {
+    assert_page("league_replay_win", &format!("/league_replay?replay_id=66d4000000000000000000a1&user_id={TAKA}"), StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn lost_replay() {
+    assert_page("league_replay_loss", &format!("/league_replay?replay_id=66d4000000000000000000a2&user_id={TAKA}"), StatusCode::OK).await;
+}
+
+/// A round missing a player, a round nobody survived and an hour long round.
+#[tokio::test]
+async fn odd_rounds_replay() {
+    assert_page("league_replay_odd_rounds", &format!("/league_replay?replay_id=66d4000000000000000000a3&user_id={TAKA}"), StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn odd_rounds_replay_from_the_other_side() {
+    assert_page("league_replay_odd_rounds_opponent", &format!("/league_replay?replay_id=66d4000000000000000000a3&user_id={VINCEHD}"), StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn multiplayer_replay() {
+    assert_page("league_replay_multi", &format!("/league_replay?replay_id=66d4000000000000000000a4&user_id={TAKA}"), StatusCode::OK).await;
+}
+
+#[tokio::test]
+async fn replay_without_the_player() {
+    assert_page("league_replay_missing_player", &format!("/league_replay?replay_id=66d4000000000000000000a1&user_id={VINCEHD}"), StatusCode::UNPROCESSABLE_ENTITY).await;
+}
+
+#[tokio::test]
+async fn unknown_replay() {
+    let response = get(&format!("/league_replay?replay_id=66d40000000000000000ffff&user_id={TAKA}")).await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn replay_card() {
+    let response = get(&format!("/league_replay.png?replay_id=66d4000000000000000000a1&user_id={TAKA}")).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.content_type, "image/png");
+}
+
+#[tokio::test]
+async fn h2h_only_counts_matches_against_the_opponent() {
+    let response = get("/api/league_h2h?user_id=taka&opponent_id=osk").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let page: serde_json::Value = serde_json::from_str(&response.body).expect("Not json");
+    let matches = page["matches"].as_array().expect("No matches");
+    assert_eq!(matches.len(), 1, "{matches:?}");
+    assert_eq!(matches[0]["replay_id"], "66d4000000000000000000a1");
+    assert_eq!(page["wins"], 1);
+}
+
+#[tokio::test]
+async fn h2h_without_matches() {
+    let response = get(&format!("/api/league_h2h?user_id={TAKA}&opponent_id=tetrabot")).await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+/// The recent records end with a multiplayer match, which isn't part of the session.
+#[tokio::test]
+async fn session_by_username() {
+    let response = get("/api/league_session?user_id=taka&count=4").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let page: serde_json::Value = serde_json::from_str(&response.body).expect("Not json");
+    assert_eq!(page["matches"].as_array().expect("No matches").len(), 3);
+    assert_eq!((page["wins"].as_u64(), page["losses"].as_u64()), (Some(2), Some(1)));
+}
+
+/// The opponent shown for `game_num` among taka's recent matches, asked for by username.
+async fn recent_opponent(game_num: &str) -> String {
+    let response = get(&format!("/api/league_recent?user_id=taka&game_num={}", urlencoding::encode(game_num))).await;
+    assert_eq!(response.status, StatusCode::OK, "{game_num}: {}", response.body);
+
+    let page: serde_json::Value = serde_json::from_str(&response.body).expect("Not json");
+    page["right_username"].as_str().expect("Not a duel").to_string()
+}
+
+#[tokio::test]
+async fn relative_selectors() {
+    assert_eq!(recent_opponent("latest win").await, "osk");
+    assert_eq!(recent_opponent("latest loss").await, "czsmall");
+    assert_eq!(recent_opponent("latest vs vincehd").await, "vincehd");
+    assert_eq!(recent_opponent("2").await, "czsmall");
+}
+
+#[tokio::test]
+async fn relative_selector_without_a_match() {
+    let response = get("/api/league_recent?user_id=taka&game_num=latest%20vs%20tetrabot").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+/// Replays mostly know players by id, asking by username must show the same page, rounds included.
+#[tokio::test]
+async fn replay_by_username() {
+    let by_username = get("/api/league_replay?replay_id=66d4000000000000000000a3&user_id=VinceHD").await;
+    let by_id = get(&format!("/api/league_replay?replay_id=66d4000000000000000000a3&user_id={VINCEHD}")).await;
+
+    assert_eq!(by_username.status, StatusCode::OK, "{}", by_username.body);
+    assert_eq!(by_username.body, by_id.body);
+    assert!(by_id.body.contains("\"left_username\":\"vincehd\""), "{}", by_id.body);
+}
+
+/// `data` comes straight from whoever builds the link.
+#[tokio::test]
+async fn hostile_replay_data() {
+    let average = |username: &str| serde_json::json!({ "username": username, "pps": 2.0, "apm": 60.0, "vs": 120.0, "score": 3 });
+    let data = serde_json::json!({
+        "league_record": { "averages": { "left": average("<script>alert(1)</script>"), "right": average("\"'><img src=x onerror=alert(2)>") }, "rounds": [] },
+        "ts": "2024-09-01T18:30:00.000Z",
+    });
+    let query = urlencoding::encode(&data.to_string()).into_owned();
+
+    let response = get(&format!("/league_replay_from_data?data={query}")).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_escaped(&response.body);
+    assert!(response.body.contains("&quot;&#x27;&gt;&lt;img src=x onerror=alert(2)&gt;"), "{}", response.body);
+
+    // the image card is drawn from the same record
+    let league_record = serde_json::from_value(data["league_record"].clone()).expect("Not a league record");
+    let page = crate::tetra::TetraHtmlPage::from_league_record(league_record, chrono::Utc::now());
+    let svg = crate::templates::render("tetra/index.svg", &page).expect("Couldn't render the card");
+    assert_escaped(&svg);
+    assert!(svg.contains("&lt;script&gt;alert(1)&lt;&#x2f;script&gt;"), "{svg}");
+}
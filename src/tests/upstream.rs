@@ -0,0 +1,140 @@
+use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::{Duration, Instant}};
+
+use axum::{http::{header, HeaderMap, HeaderValue, StatusCode}, routing::get, Router};
+use moka::future::Cache;
+
+use crate::{single_flight::SingleFlight, upstream::{self, CircuitOpen, UpstreamClient}};
+
+/// What a scripted TETR.IO answers: a status, and how many seconds to come back after for a 429.
+type Answer = (StatusCode, Option<&'static str>);
+
+/// A TETR.IO of its own answering `answers` in turn, then the last one forever, after `delay`.
+///
+/// Returns its url and how many requests it got.
+async fn scripted(answers: Vec<Answer>, delay: Duration) -> (String, Arc<AtomicUsize>) {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&hits);
+    let router = Router::new().route("/", get(move || {
+        let hit = counted.fetch_add(1, Ordering::SeqCst);
+        let (status, retry_after) = answers[hit.min(answers.len() - 1)];
+
+        async move {
+            tokio::time::sleep(delay).await;
+
+            let mut headers = HeaderMap::new();
+            if let Some(seconds) = retry_after {
+                headers.insert(header::RETRY_AFTER, HeaderValue::from_static(seconds));
+            }
+            (status, headers, "{}")
+        }
+    }));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("Couldn't bind the scripted TETR.IO");
+    let addr = listener.local_addr().expect("The scripted TETR.IO has no address");
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    (format!("http://{addr}/"), hits)
+}
+
+/// A client the rate limit never holds up.
+fn client() -> UpstreamClient {
+    UpstreamClient::for_api(1000.0, 1000.0)
+}
+
+#[tokio::test]
+async fn waits_as_long_as_rate_limits_ask() {
+    let (url, hits) = scripted(vec![(StatusCode::TOO_MANY_REQUESTS, Some("1")), (StatusCode::OK, None)], Duration::ZERO).await;
+
+    let started = Instant::now();
+    client().get(&url, None).await.expect("The retry failed");
+
+    assert!(started.elapsed() >= Duration::from_secs(1), "retried after {:?}", started.elapsed());
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn gives_up_on_waits_longer_than_the_backoff() {
+    let (url, hits) = scripted(vec![(StatusCode::TOO_MANY_REQUESTS, Some("3600"))], Duration::ZERO).await;
+
+    assert!(client().get(&url, None).await.is_err());
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn backs_off_on_server_errors() {
+    let (url, hits) = scripted(vec![(StatusCode::INTERNAL_SERVER_ERROR, None), (StatusCode::BAD_GATEWAY, None), (StatusCode::OK, None)], Duration::ZERO).await;
+
+    let started = Instant::now();
+    client().get(&url, None).await.expect("The retries failed");
+
+    // 500ms, then 1s
+    assert!(started.elapsed() >= Duration::from_millis(1500), "retried after {:?}", started.elapsed());
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn client_errors_are_not_retried() {
+    let (url, hits) = scripted(vec![(StatusCode::NOT_FOUND, None)], Duration::ZERO).await;
+
+    let (status, _) = client().get_with_status(&url, None).await.expect("A 404 is an answer");
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn breaker_opens_after_repeated_failures() {
+    let (url, hits) = scripted(vec![(StatusCode::TOO_MANY_REQUESTS, Some("3600"))], Duration::ZERO).await;
+    let client = client();
+
+    for _ in 0..5 {
+        let error = client.get(&url, None).await.expect_err("TETR.IO is failing");
+        assert!(error.downcast_ref::<CircuitOpen>().is_none(), "{error}");
+    }
+
+    let error = client.get(&url, None).await.expect_err("The breaker should be open");
+    assert!(error.downcast_ref::<CircuitOpen>().is_some(), "{error}");
+    assert_eq!(hits.load(Ordering::SeqCst), 5);
+}
+
+#[tokio::test]
+async fn stale_answers_cover_failures() {
+    let stale = Cache::builder().build();
+
+    assert_eq!(upstream::with_stale_fallback(&stale, "taka", async { anyhow::Ok(1) }).await.ok(), Some(1));
+    assert_eq!(upstream::with_stale_fallback(&stale, "taka", async { anyhow::bail!("TETR.IO is down") }).await.ok(), Some(1));
+    assert!(upstream::with_stale_fallback::<i32, _>(&stale, "osk", async { anyhow::bail!("TETR.IO is down") }).await.is_err());
+}
+
+/// The way `AppState` fetches user info and summaries.
+#[tokio::test]
+async fn concurrent_callers_share_one_request() {
+    let (url, hits) = scripted(vec![(StatusCode::OK, None)], Duration::from_millis(200)).await;
+    let client = client();
+    let flights = SingleFlight::default();
+
+    let callers = (0..8).map(|_| flights.run("taka", || async { client.get(&url, None).await.map_err(Arc::new) }));
+    let answers = futures::future::join_all(callers).await;
+
+    assert!(answers.iter().all(Result::is_ok));
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+/// What `/ready` calls: once, past an open breaker, and not for long.
+#[tokio::test]
+async fn probes_make_a_single_short_attempt() {
+    let (url, hits) = scripted(vec![(StatusCode::TOO_MANY_REQUESTS, Some("3600"))], Duration::ZERO).await;
+    let client = client();
+    for _ in 0..5 {
+        let _ = client.get(&url, None).await;
+    }
+
+    let (status, _) = client.probe(&url, None, Duration::from_secs(1)).await.expect("The probe should get through");
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(hits.load(Ordering::SeqCst), 6);
+
+    let (slow_url, _) = scripted(vec![(StatusCode::OK, None)], Duration::from_secs(5)).await;
+    let started = Instant::now();
+    assert!(client.probe(&slow_url, None, Duration::from_millis(100)).await.is_err());
+    assert!(started.elapsed() < Duration::from_secs(1), "gave up after {:?}", started.elapsed());
+}
@@ -1,257 +1,219 @@
-// can you make this file cleaner?  
+// can you make this file cleaner?
 
 
 
 use std::{str::FromStr, sync::Arc, time::Duration};
 
-use axum::{extract::{Path, State}, response::{Html, IntoResponse}};
+use axum::{extract::{Path, State}, http::HeaderMap, response::Response};
 
 use chrono::Utc;
-use tetrio_api::models::users::{summaries::{blitz::BlitzSummary, sprint::SprintSummary, tetra_league::LeagueSummary}, user_badge::UserBadge, user_distinguishment::UserDistinguishment, user_info::UserInfo, user_role::UserRole};
-
-use crate::AppState;
-
-const TETO_HTML_BOT_FILE: &str = include_str!("../assets/teto/bot.html");
-const TETO_HTML_BANNED_FILE: &str = include_str!("../assets/teto/banned.html");
-const TETO_HTML_FILE: &str = include_str!("../assets/teto/index.html");
-const TETO_HTML_FLAG: &str = "<img class=\"flag\" src=\"https://tetr.io/res/flags/{{country_code}}.png\">";
-const TETO_HTML_BANNER: &str = "<img class=\"tetra_modal_banner ns\" src=\"{{banner_url}}\">";
-const TETO_HTML_MOD_BADGE: &str = "<img class=\"mod_badge\" src=\"{{mod_icon}}\" title=\"This person has unlimited permissions on TETR.IO.\" alt=\"Sysop\">";
-const TETO_HTML_BANNER_SEP: &str = "<div class=\"tetra_modal_banner_sep ns\"></div>";
-const TETO_HTML_GAME_TIME: &str = "<div class=\"tetra_tag_gametime\" title=\"Total time played\">{{time}}<span>{{unit}}</span></div>";
-const TETO_HTML_SUPPORTER: &str = "<img class=\"supporter_badge\" src=\"https://tetr.io/res/supporter{{supporter_tier}}.png\" title=\"This person is supporting TETR.IO ♥\" alt=\"Supporter\">";
-const TETO_HTML_RECORDS: &str = "<div class=\"tetra_modal_records flex-row\">
-{{tetra_league}}
-{{sprint}}
-{{blitz}}";
-
-const TETO_TWC_DISTINGUISHMENT: &str = r#"<div class="tetra_distinguishment ns tetra_distinguishment_twc" data-detail="{{detail}}"><h1>TETR.IO WORLD CHAMPION</h1><p>{{detail}} TETR.IO WORLD CHAMPIONSHIP</p></div>"#;
-
-const TETO_HTML_RECORDS_TETRA_LEAGUE: &str = r#"<div class="tetra_modal_record flex-item tetra_modal_record_league tetra_modal_record_league_active">
-							<div class="tetra_modal_record_header">
-								<h6>TETRA LEAGUE</h6>
-								<div class="standingset">
-									
-										{{country_ranking}}
-									
-									
-										<div class="standingset_global " data-digits="4">#<span>{{global_ranking}}</span></div>
-									
-								</div>
-							</div>
-							<h5 title="14173.95200992754"><img src="{{rank}}">{{tr}}<span class="ms">TR</span></h5>
-							<h3><span>{{apm}}</span> apm <span>{{pps}}</span> pps <span>{{vs}}</span> vs</h3></div>"#;
-
-const TETO_HTML_RECORDS_TETRA_LEAGUE_RATING: &str = r#"<div class="tetra_modal_record flex-item tetra_modal_record_league">
-							<div class="tetra_modal_record_header"><h6>TETRA LEAGUE</h6></div>
-							<h5>{{games_played}}<span class="ms">/10 rating games</span></h5>
-							<h3><span>{{games_won}}</span> games won</h3>
-						</div>
-"#;
-
-const TETO_HTML_RECORDS_COUNTRY_RANKING: &str = r#"<div class="standingset_local">{{country_flag}} #<span>{{country_ranking}}</span></div>"#;
-
-const TETO_HTML_RECORDS_SPRINT: &str = r#"<div class="tetra_modal_record flex-item">
-						<div class="tetra_modal_record_header">
-							<h6>40 LINES</h6>
-							<div class="standingset">
-								
-									{{country_ranking}}
-								
-								
-									<div class="standingset_global " data-digits="6">#<span>{{global_ranking}}</span></div>
-								
-							</div>
-						</div>
-						<h5>{{sprint_time}}<span class="ms">{{sprint_time_ms}}</span></h5>
-						<h3><span>{{date}}</span> ago</h3></div>"#;
-
-const TETO_HTML_RECORDS_BLITZ: &str = r#"<div class="tetra_modal_record flex-item">
-						<div class="tetra_modal_record_header">
-							<h6>BLITZ</h6>
-							<div class="standingset">
-								
-									{{country_ranking}}
-								
-								
-									<div class="standingset_global " data-digits="5">#<span>{{global_ranking}}</span></div>
-								
-							</div>
-						</div>
-						<h5>{{blitz_score}}</h5>
-						<h3><span>{{date}}</span> ago</h3></div>"#;
-
-const TETO_HTML_BADGES: &str = "<div class=\"tetra_badge_holder ns\">{{badges}}</div>";
-
-const TETO_HTML_BADGE: &str = "<img
-class=\"tetra_badge\" src=\"{{badge}}\" title=\"Huge Supporter\" style=\"--i: 0;\">";
-
-const TETO_HTML_TETRA_LEAGUE_CHAMPION_DISTINGUISHMENT: &str = 
-"<div class=\"tetra_distinguishment ns tetra_distinguishment_champion\" data-detail=\"league\"><h1>TETRA LEAGUE CHAMPION</h1></div>";
-const TETO_HTML_SPRINT_CHAMPION_DISTINGUISHMENT: &str = 
-"<div class=\"tetra_distinguishment ns tetra_distinguishment_champion\" data-detail=\"40l\"><h1>40 LINES CHAMPION</h1></div>";
-const TETO_HTML_BLITZ_CHAMPION_CHAMPION_DISTINGUISHMENT: &str = "<div class=\"tetra_distinguishment ns tetra_distinguishment_champion\" data-detail=\"blitz\"><h1>BLITZ CHAMPION</h1></div>";
-const TETO_HTML_STAFF_DISTINGUISHMENT: &str = "<div class=\"tetra_distinguishment ns tetra_distinguishment_staff\" data-detail=\"{{staff_type}}\">
-<h1>{{title}}</h1>
-{{teto_staff_subtitle}}
-</div>";
-
-const TETO_HTML_TOTAL_GAMES: &str = "<div class=\"tetra_tag_record\" title=\"Online games won / online games played\">
-{{online_games_won}}{{online_games_played}}
-</div>";
-
-const TETO_HTML_GAMES_WON: &str = "<span>{{online_games_won}}</span>";
-
-const TETO_HTML_BAD_STANDING: &str = "<div class=\"tetra_badstanding ns\"><h1>BAD STANDING</h1><p>one or more recent bans on record</p></div>";
-const TETO_HTML_STAFF_DISTINGUISHMENT_SUBTITLE: &str = "<p>{{subtitle_text}}</p>";
-const TETO_HTML_STAFF_DISTINGUISHMENT_TETRIO_LOGO: &str = "<img src=\"https://tetr.io/res/tetrio-logo.svg\" style=\"filter: invert(1);\">";
-const TETO_HTML_STAFF_DISTINGUISHMENT_OSK: &str = "<img src=\"https://tetr.io/res/osk.svg\">";
+use minijinja::{context, Value};
+use serde::Serialize;
+use tetrio_api::models::{packet::Packet, users::{summaries::{AllSummaries, blitz::BlitzSummary, sprint::SprintSummary, tetra_league::LeagueSummary}, user_badge::UserBadge, user_distinguishment::UserDistinguishment, user_info::UserInfo, user_role::UserRole}};
 
+use crate::{asset_mirror::ASSETS_ROUTE, error::{AppError, JsonError}, image_proxy, page_cache, templates::{self, escape_html, Contract, TrustedHtml}, AppState};
+
+fn teto_res_url() -> String {
+    format!("{ASSETS_ROUTE}/res")
+}
 
 
+pub(crate) fn template_contracts() -> Vec<Contract> {
+    let page = Value::from_serialize(TetoHTMLParams::default());
+
+    vec![
+        Contract::new("teto/index.html", page.clone()),
+        Contract::new("teto/bot.html", Value::from_serialize(TetoBotParams::default())),
+        Contract::new("teto/banned.html", Value::from_serialize(TetoBannedParams::default())),
+        Contract::new("teto/partials/flag.html", page.clone()),
+        Contract::new("teto/partials/badge.html", context! { badge => String::new(), ..page.clone() }),
+        Contract::new("teto/partials/distinguishment.html", context! { distinguishment => Distinguishment::default(), ..page.clone() }),
+        Contract::new("teto/partials/country_ranking.html", context! { record => SprintRecord::default(), ..page.clone() }),
+        Contract::new("teto/partials/records_tetra_league.html", context! { record => LeagueRecord::default(), ..page.clone() }),
+        Contract::new("teto/partials/records_tetra_league_rating.html", context! { record => LeagueRatingRecord::default(), ..page.clone() }),
+        Contract::new("teto/partials/records_sprint.html", context! { record => SprintRecord::default(), ..page.clone() }),
+        Contract::new("teto/partials/records_blitz.html", context! { record => BlitzRecord::default(), ..page.clone() }),
+        Contract::new("teto/index.svg", page),
+        Contract::new("teto/bot.svg", Value::from_serialize(TetoBotParams::default())),
+        Contract::new("teto/banned.svg", Value::from_serialize(TetoBannedParams::default())),
+    ]
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum TetoPage {
+    Banned(TetoBannedParams),
+    Bot(TetoBotParams),
+    Profile(Box<TetoHTMLParams>),
+}
+
+impl TetoPage {
+    fn render(&self, extension: &str) -> Result<String, minijinja::Error> {
+        match self {
+            TetoPage::Banned(params) => templates::render(&format!("teto/banned.{extension}"), params),
+            TetoPage::Bot(params) => templates::render(&format!("teto/bot.{extension}"), params),
+            TetoPage::Profile(params) => templates::render(&format!("teto/index.{extension}"), params),
+        }
+    }
+}
 
 fn level_from_xp(x: f64) -> f64 {
     // simplify the formula
     (x / 500.0).powf(0.6) + x / (5000.0 + (f64::max(0.0, x - 4.0 * 10.0f64.powi(6)) / 5000.0)) + 1.0
 }
 
-async fn handle_banned(data: &UserInfo) -> impl IntoResponse {
-    let avatar_rev = data.avatar_revision.unwrap_or(0);
-    let avatar = if avatar_rev != 0 {
-        format!("https://tetr.io/user-content/avatars/{}.jpg?rv={}", data.id, avatar_rev)
-    }
-    else {
-        String::from("/teto/unkown_avatar.webp")
-    };
+#[derive(Serialize, Default)]
+pub(crate) struct TetoBannedParams {
+    avatar: String,
+    username: String,
+}
 
-    Html(
-        TETO_HTML_BANNED_FILE.replacen("{{avatar}}",&avatar, 1)
-        .replacen("{{username}}", &data.username.to_uppercase(), 1)
-    )
+#[derive(Serialize, Default)]
+pub(crate) struct TetoBotParams {
+    avatar: String,
+    username: String,
+    owner: String,
 }
 
-async fn handle_bot(data: &UserInfo) -> impl IntoResponse {
-    let bot_owner = match &data.botmaster {
+fn handle_banned(data: &UserInfo) -> TetoPage {
+    let avatar = parse_avatar(data.avatar_revision.unwrap_or(0), &data.id);
+
+    TetoPage::Banned(TetoBannedParams {
+        avatar,
+        username: data.username.to_uppercase(),
+    })
+}
+
+fn handle_bot(data: &UserInfo) -> TetoPage {
+    let owner = match &data.botmaster {
         Some(data) => data.to_uppercase(),
         None => String::new()
     };
-    let avatar_rev = data.avatar_revision.unwrap_or(0);
-    let avatar = if avatar_rev != 0 {
-        format!("https://tetr.io/user-content/avatars/{}.jpg?rv={}", data.id, avatar_rev)
-    }
-    else {
-        String::from("/teto/unkown_avatar.webp")
-    };
+    let avatar = parse_avatar(data.avatar_revision.unwrap_or(0), &data.id);
 
-    Html(
-        TETO_HTML_BOT_FILE.replacen("{{avatar}}",&avatar, 1)
-        .replacen("{{username}}", &data.username.to_uppercase(), 1)
-        .replacen("{{owner}}", &bot_owner, 1)   
-    )
+    TetoPage::Bot(TetoBotParams {
+        avatar,
+        username: data.username.to_uppercase(),
+        owner,
+    })
 }
 
-fn parse_banner(has_banner: bool, banner_rev: i64, user_id: &str) -> String {
+fn parse_banner(has_banner: bool, banner_rev: i64, user_id: &str) -> Option<String> {
     if has_banner {
-
-        TETO_HTML_BANNER.replacen("{{banner_url}}", &format!(
-            "https://tetr.io/user-content/banners/{user_id}.jpg?rv={banner_rev}"
-        ), 1) + TETO_HTML_BANNER_SEP
+        Some(image_proxy::banner_url(user_id, banner_rev))
     } else {
-        String::new()
+        None
     }
 }
 
 fn parse_avatar(avatar_rev: i64, user_id: &str) -> String {
     if avatar_rev != 0 {
-        format!("https://tetr.io/user-content/avatars/{user_id}.jpg?rv={avatar_rev}")
+        image_proxy::avatar_url(user_id, avatar_rev)
     }
     else {
-        String::from("/teto/unkown_avatar.webp")
+        String::from(image_proxy::UNKNOWN_AVATAR_PATH)
     }
 }
 
-fn parse_bad_standing(bad_standing: Option<bool>) -> &'static str {
-    if let Some(bad_standing) = bad_standing {
-        if bad_standing {
-            TETO_HTML_BAD_STANDING
-        }
-        else {
-            ""
-        }
-    } else {
-        ""
-    }
+fn has_banner(banner_rev: i64, supporter_tier: i64) -> bool {
+    banner_rev != 0 && supporter_tier != 0
 }
 
-fn has_banner(banner_rev: i64, supporter_tier: i64) -> bool {
-    return banner_rev != 0 && supporter_tier != 0;
-}
-
-fn parse_mod_badge(role: &UserRole) -> String {
-    match role {
-        UserRole::Anon => String::new(),
-        UserRole::User => String::new(),
-        UserRole::Bot => String::new(),
-        UserRole::Banned => String::new(),
-        UserRole::Mod => TETO_HTML_MOD_BADGE.replace("{{mod_icon}}", "https://tetr.io/res/verified-mod.png"),
-        UserRole::Admin => TETO_HTML_MOD_BADGE.replace("{{mod_icon}}", "https://tetr.io/res/verified-admin.png"),
-        UserRole::SysOp => TETO_HTML_MOD_BADGE.replace("{{mod_icon}}", "https://tetr.io/res/verified-sysop.png"),
-        UserRole::HalfMod => TETO_HTML_MOD_BADGE.replace("{{mod_icon}}", "https://tetr.io/res/verified-halfmod.png"),
-        UserRole::Hidden => String::new(),
-        UserRole::Unknown(_) => String::new(),
-    }
-}
-
-fn parse_distinguishment(distinguishment: &Option<UserDistinguishment>) -> Option<String> {
-    if let Some(distinguishment) = distinguishment {
-        if let Some(detail) = &distinguishment.detail {
-
-            if distinguishment.distinguishment_type.as_str() == "twc" {
-                return Some(TETO_TWC_DISTINGUISHMENT.replacen("{{detail}}", detail.as_ref(), 2))
-            }
-
-            let header = if let Some(header) = &distinguishment.header {
-                header.replace("%tetrio%", TETO_HTML_STAFF_DISTINGUISHMENT_TETRIO_LOGO)
-                .replace("%osk%", TETO_HTML_STAFF_DISTINGUISHMENT_OSK)
-            }
-            else {
-                String::new()
-            };
-            
-            let footer = if let Some(footer) = &distinguishment.footer {
-                TETO_HTML_STAFF_DISTINGUISHMENT_SUBTITLE.replacen("{{subtitle_text}}",
-                &footer
-                .replace("%tetrio%", TETO_HTML_STAFF_DISTINGUISHMENT_TETRIO_LOGO)
-                .replace("%osk%", TETO_HTML_STAFF_DISTINGUISHMENT_OSK)
-                , 1)
-            }
-            else {
-                String::new()
-            };
-
-
-            Some(TETO_HTML_STAFF_DISTINGUISHMENT
-                .replacen("{{staff_type}}", &detail, 1)
-                .replacen("{{title}}", &header, 1)
-                .replacen("{{teto_staff_subtitle}}", &footer, 1)
-            )
-        }
-        else {
-            None
+fn parse_mod_badge(role: &UserRole) -> Option<String> {
+    let icon = match role {
+        UserRole::Anon => return None,
+        UserRole::User => return None,
+        UserRole::Bot => return None,
+        UserRole::Banned => return None,
+        UserRole::Mod => "verified-mod.png",
+        UserRole::Admin => "verified-admin.png",
+        UserRole::SysOp => "verified-sysop.png",
+        UserRole::HalfMod => "verified-halfmod.png",
+        UserRole::Hidden => return None,
+        UserRole::Unknown(_) => return None,
+    };
+
+    Some(format!("{}/{icon}", teto_res_url()))
+}
+
+#[derive(Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum DistinguishmentKind {
+    Twc,
+    Champion,
+    #[default]
+    Staff,
+}
+
+#[derive(Serialize, Default, Clone)]
+struct Distinguishment {
+    kind: DistinguishmentKind,
+    detail: String,
+    title: TrustedHtml,
+    subtitle: Option<TrustedHtml>,
+    /// `title` as plain text, for the image cards.
+    label: String,
+}
+
+impl Distinguishment {
+    fn champion(detail: &str, title: &str) -> Self {
+        Self {
+            kind: DistinguishmentKind::Champion,
+            detail: detail.to_string(),
+            title: TrustedHtml::new(escape_html(title)),
+            subtitle: None,
+            label: title.to_string(),
         }
-    } else {
-        None
     }
 }
 
+/// Escapes a distinguishment header or footer, then swaps its `%tetrio%` and `%osk%` markers for the logos.
+fn replace_distinguishment_logos(text: &str) -> TrustedHtml {
+    TrustedHtml::new(
+        escape_html(text)
+            .replace("%tetrio%", &format!("<img src=\"{}/tetrio-logo.svg\" style=\"filter: invert(1);\">", teto_res_url()))
+            .replace("%osk%", &format!("<img src=\"{}/osk.svg\">", teto_res_url()))
+    )
+}
 
-fn parse_country_flag(country: Option<&str>) -> String {
-    if let Some(country) = &country {
-        TETO_HTML_FLAG.replacen("{{country_code}}", &country.to_lowercase(), 1)
-    }
-    else {
-        String::new()
-    }
+fn parse_distinguishment(distinguishment: &Option<UserDistinguishment>) -> Option<Distinguishment> {
+    let distinguishment = distinguishment.as_ref()?;
+    let detail = distinguishment.detail.as_ref()?;
+
+    if distinguishment.distinguishment_type.as_str() == "twc" {
+        return Some(Distinguishment {
+            kind: DistinguishmentKind::Twc,
+            detail: detail.to_string(),
+            title: TrustedHtml::default(),
+            subtitle: None,
+            label: String::from("TETR.IO WORLD CHAMPION"),
+        })
+    }
+
+    let title = distinguishment.header.as_deref()
+        .map(replace_distinguishment_logos)
+        .unwrap_or_default();
+
+    let subtitle = distinguishment.footer.as_deref()
+        .map(replace_distinguishment_logos);
+
+    let label = distinguishment.header.as_deref()
+        .unwrap_or_default()
+        .replace("%tetrio%", "TETR.IO")
+        .replace("%osk%", "osk");
+
+    Some(Distinguishment {
+        kind: DistinguishmentKind::Staff,
+        detail: detail.to_string(),
+        title,
+        subtitle,
+        label,
+    })
+}
+
+
+fn parse_country_flag(country: Option<&str>) -> Option<String> {
+    country.map(|country| format!("{}/flags/{}.png", teto_res_url(), country.to_lowercase()))
 }
 
 fn parse_level_tag(level: u64) -> String {
@@ -259,74 +221,62 @@ fn parse_level_tag(level: u64) -> String {
         let shape_color = (level / 10) % 10;
         let shape = (level / 100) % 5;
         let badge_color = (level / 500) % 10;
-        format!("lt_shape_{shape} lt_badge_color_{badge_color} lt_shape_color_{shape_color}") 
+        format!("lt_shape_{shape} lt_badge_color_{badge_color} lt_shape_color_{shape_color}")
     }
     else {
         String::from("lt_golden")
     }
 }
 
-fn parse_total_games(gamesplayed: i64, gameswon: i64) -> String {
-    {
-        if gamesplayed != -1 || gameswon != -1 {
-            let games_won = if gameswon != -1 {
-                TETO_HTML_GAMES_WON.replacen("{{online_games_won}}", &gameswon.to_string(), 1)
-            } else {
-                String::new()
-            };
+#[derive(Serialize, Default)]
+struct TotalGames {
+    won: Option<i64>,
+    played: Option<i64>,
+}
 
-            let games_played = if gamesplayed != -1 {
-                format!(" / {}", gamesplayed)
-            } else {
-                String::new()
-            };
+fn parse_total_games(gamesplayed: i64, gameswon: i64) -> Option<TotalGames> {
+    if gamesplayed == -1 && gameswon == -1 {
+        return None;
+    }
 
-            TETO_HTML_TOTAL_GAMES.replacen("{{online_games_won}}", games_won.as_ref(), 1).replacen("{{online_games_played}}", games_played.as_ref(), 1)
-        }
-        else {
-            String::new()
-        }
+    Some(TotalGames {
+        won: (gameswon != -1).then_some(gameswon),
+        played: (gamesplayed != -1).then_some(gamesplayed),
+    })
+}
 
-    }
+#[derive(Serialize, Default)]
+struct GameTime {
+    time: u64,
+    unit: &'static str,
 }
 
-fn parse_gametime(gametime: f64) -> String {
-    if gametime != -1.0 {
-        let playtime = Duration::from_secs_f64(gametime);
-        let seconds = playtime.as_secs();
-        let (time, unit) = if seconds > 3600 {
-            (seconds / 3600, "H")
-        } else if seconds > 60 {
-            (seconds / 60, "M")
-        } else {
-            (seconds, "S")
-        };
+fn parse_gametime(gametime: f64) -> Option<GameTime> {
+    if gametime == -1.0 {
+        return None;
+    }
 
-        TETO_HTML_GAME_TIME.replacen("{{time}}", &time.to_string(), 1).replacen("{{unit}}", unit, 1)
+    let playtime = Duration::from_secs_f64(gametime);
+    let seconds = playtime.as_secs();
+    let (time, unit) = if seconds > 3600 {
+        (seconds / 3600, "H")
+    } else if seconds > 60 {
+        (seconds / 60, "M")
     } else {
-        String::new()
-    }
-}
+        (seconds, "S")
+    };
 
-fn parse_supporter_badge(supporter_tier: i64) -> String {
-    if supporter_tier != 0 {
-        TETO_HTML_SUPPORTER.replacen("{{supporter_tier}}", &supporter_tier.to_string(), 1)
-    }
-    else {
-        String::new()
-    }
+    Some(GameTime { time, unit })
 }
 
-fn parse_user_badges(badges: &[UserBadge]) -> String {
-    if !badges.is_empty() {
-        let badges = badges.iter().map(|e| {
-            <&str>::clone(&TETO_HTML_BADGE).replacen("{{badge}}", &format!("https://tetr.io/res/badges/{}.png", e.id), 1)
-        }).fold(String::new(), |g, next| {g + "\n" + &next});
+fn parse_supporter_tier(supporter_tier: i64) -> Option<i64> {
+    (supporter_tier != 0).then_some(supporter_tier)
+}
 
-        TETO_HTML_BADGES.replacen("{{badges}}", &badges, 1)
-    } else {
-        String::new()
-    }
+fn parse_user_badges(badges: &[UserBadge]) -> Vec<String> {
+    badges.iter()
+        .map(|badge| format!("{}/badges/{}.png", teto_res_url(), badge.id))
+        .collect()
 }
 
 fn parse_duration_since(duration: &chrono::Duration) -> String {
@@ -367,19 +317,19 @@ fn parse_duration_since(duration: &chrono::Duration) -> String {
     }
 }
 
-struct ParsedResultWithDistinguishment {
-    distinguishment: Option<&'static str>,
-    result: String
+struct ParsedResultWithDistinguishment<T> {
+    distinguishment: Option<Distinguishment>,
+    result: Option<T>
 }
 
 fn parse_blitz_score_number(blitz: u64) -> String {
     let (million, blitz) = (blitz / 1_000_000, blitz % 1_000_000);
 
     let (thousands, blitz) = (blitz / 1_000, blitz % 1_000);
-    
+
     let units = blitz;
 
-    let units = if thousands != 0 {
+    let units = if million != 0 || thousands != 0 {
         format!("{units:0width$}", width=3)
     }
     else {
@@ -396,7 +346,7 @@ fn parse_blitz_score_number(blitz: u64) -> String {
         String::new()
     };
 
-    let million = 
+    let million =
         if million != 0 {
             format!("{million},")
         }
@@ -405,258 +355,275 @@ fn parse_blitz_score_number(blitz: u64) -> String {
     };
 
 
-    return format!("{}{}{}", million, thousands, units);
+    format!("{}{}{}", million, thousands, units)
 }
 
-fn parse_blitz_score(summary: &BlitzSummary) -> Result<ParsedResultWithDistinguishment, &'static str> {
-    let mut result = ParsedResultWithDistinguishment {
-        distinguishment: None,
-        result: String::new()
-    };
-    
-    result.result = if let Some(record) = &summary.record {
-
-            let time = match chrono::DateTime::<Utc>::from_str(record.ts.as_ref()){ 
-                Ok(e) => e,
-                Err(_) => return Err("<h1> Couldn't parse Blitz record </h1>")
-            };
-
-            let now = Utc::now();
-            let duration = now.signed_duration_since(time);
-
-            let ago = parse_duration_since(&duration);
-        
-            let blitz = record.results.stats.score as u64;
-        
-            let blitz_score = parse_blitz_score_number(blitz);
-
-
-            result.distinguishment = if summary.rank == 1 {
-                Some(TETO_HTML_BLITZ_CHAMPION_CHAMPION_DISTINGUISHMENT)
-            }
-            else {
-                None
-            };
-
-            let country_ranking = if summary.rank_local != -1 {
-                TETO_HTML_RECORDS_COUNTRY_RANKING.replacen("{{country_ranking}}", &&parse_blitz_score_number(summary.rank_local as u64).to_string(), 1)
-            }  
-            else {
-                String::new()
-            };    
-
-
-            TETO_HTML_RECORDS_BLITZ
-                .replacen("{{blitz_score}}", &blitz_score, 1)
-                .replacen("{{date}}", &ago, 1)
-                .replacen("{{global_ranking}}", &parse_blitz_score_number(summary.rank as u64).to_string(), 1)
-                .replacen("{{country_ranking}}", &country_ranking, 1)
-
-        } else {
-            String::new()
-        };
+fn parse_country_ranking(rank_local: i64) -> Option<String> {
+    (rank_local != -1).then(|| parse_blitz_score_number(rank_local as u64))
+}
 
-    Ok(result)
+#[derive(Serialize, Default)]
+struct BlitzRecord {
+    score: String,
+    ago: String,
+    global_ranking: String,
+    country_ranking: Option<String>,
 }
 
+fn parse_blitz_score(summary: &BlitzSummary) -> Result<ParsedResultWithDistinguishment<BlitzRecord>, AppError> {
+    let Some(record) = &summary.record else {
+        return Ok(ParsedResultWithDistinguishment { distinguishment: None, result: None });
+    };
 
-fn parse_tetra_league(league: &LeagueSummary) -> ParsedResultWithDistinguishment {
-    let mut result = ParsedResultWithDistinguishment {
-        distinguishment: None,
-        result: String::new()
+    let time = match chrono::DateTime::<Utc>::from_str(record.ts.as_ref()){
+        Ok(e) => e,
+        Err(_) => return Err(AppError::Upstream(String::from("Couldn't parse Blitz record")))
     };
 
-    result.result = if let LeagueSummary { tr: Some(tr), gamesplayed: Some(gamesplayed), gameswon: Some(gameswon), .. } = &league {
-        if let LeagueSummary {rank: Some(rank), standing: Some(standing), standing_local: Some(standing_local), ..} = &league {
-            result.distinguishment = if *standing == 1 {
-                Some(TETO_HTML_TETRA_LEAGUE_CHAMPION_DISTINGUISHMENT)
-            } else {
-                None
-            };
-            
-            if gamesplayed == &0 {
-                String::new()
-            }
-            else if gamesplayed < &10 {
-                TETO_HTML_RECORDS_TETRA_LEAGUE_RATING
-                .replacen("{{games_played}}", &gamesplayed.to_string(), 1)
-                .replacen("{{games_won}}", &gameswon.to_string(), 1)
-            }
-            
-            else {
-
-                let country_ranking = if standing_local != &-1 {
-                    TETO_HTML_RECORDS_COUNTRY_RANKING.replacen("{{country_ranking}}", &parse_blitz_score_number(*standing_local as u64).to_string(), 1)
-                }  
-                else {
-                    String::new()
-                };          
-                TETO_HTML_RECORDS_TETRA_LEAGUE
-                .replacen("{{rank}}", &format!("https://tetr.io/res/league-ranks/{}.png", rank).to_lowercase(), 1)
-                .replacen("{{tr}}", &(tr.round()).to_string(), 1)
-                .replacen("{{vs}}", &format!("{:.2}", league.vs.unwrap_or(0.0)), 1)
-                .replacen("{{apm}}", &format!("{:.2}", league.apm.unwrap_or(0.0)), 1)
-                .replacen("{{pps}}", &format!("{:.2}", league.pps.unwrap_or(0.0)), 1)
-                .replacen("{{country_ranking}}", &country_ranking, 1)
-                .replacen("{{global_ranking}}", &parse_blitz_score_number(*standing as u64).to_string(), 1)
-            }
-        }
-        else {
-            String::new()
-        }
+    let now = Utc::now();
+    let duration = now.signed_duration_since(time);
+
+    let distinguishment = if summary.rank == 1 {
+        Some(Distinguishment::champion("blitz", "BLITZ CHAMPION"))
     }
     else {
-        String::new()
+        None
     };
 
+    Ok(ParsedResultWithDistinguishment {
+        distinguishment,
+        result: Some(BlitzRecord {
+            score: parse_blitz_score_number(record.results.stats.score as u64),
+            ago: parse_duration_since(&duration),
+            global_ranking: parse_blitz_score_number(summary.rank as u64),
+            country_ranking: parse_country_ranking(summary.rank_local),
+        })
+    })
+}
 
-    
-
+#[derive(Serialize, Default)]
+struct LeagueRatingRecord {
+    games_played: i64,
+    games_won: i64,
+}
 
-    return result;
+#[derive(Serialize, Default)]
+struct LeagueRecord {
+    rank_icon: String,
+    tr: f64,
+    apm: f64,
+    pps: f64,
+    vs: f64,
+    global_ranking: String,
+    country_ranking: Option<String>,
 }
 
+#[derive(Serialize, Default)]
+struct LeagueRecords {
+    rating: Option<LeagueRatingRecord>,
+    ranked: Option<LeagueRecord>,
+}
 
-fn parse_sprint(sprint_record: &SprintSummary) -> Result<ParsedResultWithDistinguishment, &'static str> {
+fn parse_tetra_league(league: &LeagueSummary) -> ParsedResultWithDistinguishment<LeagueRecords> {
     let mut result = ParsedResultWithDistinguishment {
         distinguishment: None,
-        result: String::new()
+        result: None
     };
 
-    result.result = if let Some(record) = &sprint_record.record {
+    let LeagueSummary { tr: Some(tr), gamesplayed: Some(gamesplayed), gameswon: Some(gameswon), .. } = &league else {
+        return result;
+    };
 
-        let time = match chrono::DateTime::<Utc>::from_str(record.ts.as_ref()){
-            Ok(v) => v,
-            Err(_) => return Err("<h1> parsing error </h1>")
-        };
+    let LeagueSummary { rank: Some(rank), standing: Some(standing), standing_local: Some(standing_local), .. } = &league else {
+        return result;
+    };
 
-        let now = Utc::now();
-        let duration = now.signed_duration_since(time);
-        let ago = parse_duration_since(&duration);
-        let sprint = record.results.stats.finaltime as u64;
-
-        let duration = Duration::from_millis(sprint);
-        let ms = duration.subsec_millis();
-        let secs = duration.as_secs() % 60;
-        let minutes = duration.as_secs() / 60;
-        let hours = minutes / 60;
-        let hours_formated = if hours != 0 {
-            format!("{}:", hours)
-        }
-        else {
-            String::new()
-        };
+    result.distinguishment = if *standing == 1 {
+        Some(Distinguishment::champion("league", "TETRA LEAGUE CHAMPION"))
+    } else {
+        None
+    };
 
-        let minutes = if hours != 0 {
-            format!("{minutes:0width$}", width = 2)
-        }
-        else {
-            minutes.to_string()
-        };
+    result.result = if gamesplayed == &0 {
+        None
+    }
+    else if gamesplayed < &10 {
+        Some(LeagueRecords {
+            rating: Some(LeagueRatingRecord {
+                games_played: *gamesplayed,
+                games_won: *gameswon,
+            }),
+            ranked: None,
+        })
+    }
+    else {
+        Some(LeagueRecords {
+            rating: None,
+            ranked: Some(LeagueRecord {
+                rank_icon: format!("{}/league-ranks/{}.png", teto_res_url(), rank.to_lowercase()),
+                tr: *tr,
+                apm: league.apm.unwrap_or(0.0),
+                pps: league.pps.unwrap_or(0.0),
+                vs: league.vs.unwrap_or(0.0),
+                global_ranking: parse_blitz_score_number(*standing as u64),
+                country_ranking: parse_country_ranking(*standing_local),
+            }),
+        })
+    };
 
-        let final_sprint_time = format!("{hours_formated}{}:{:0width$}", minutes, secs, width = 2);
+    result
+}
 
+#[derive(Serialize, Default)]
+struct SprintRecord {
+    time: String,
+    time_ms: String,
+    ago: String,
+    global_ranking: String,
+    country_ranking: Option<String>,
+}
 
+fn parse_sprint(sprint_record: &SprintSummary) -> Result<ParsedResultWithDistinguishment<SprintRecord>, AppError> {
+    let Some(record) = &sprint_record.record else {
+        return Ok(ParsedResultWithDistinguishment { distinguishment: None, result: None });
+    };
 
-        result.distinguishment = if sprint_record.rank == 1 {
-            Some(TETO_HTML_SPRINT_CHAMPION_DISTINGUISHMENT)
-        } else {
-            None
-        };
+    let time = match chrono::DateTime::<Utc>::from_str(record.ts.as_ref()){
+        Ok(v) => v,
+        Err(_) => return Err(AppError::Upstream(String::from("Couldn't parse 40 lines record")))
+    };
 
-        let country_ranking = if sprint_record.rank_local != -1 {
-            TETO_HTML_RECORDS_COUNTRY_RANKING.replacen("{{country_ranking}}", &parse_blitz_score_number(sprint_record.rank_local as u64).to_string(), 1)
-        }  
-        else {
-            String::new()
-        };          
+    let now = Utc::now();
+    let duration = now.signed_duration_since(time);
+    let ago = parse_duration_since(&duration);
+    let sprint = record.results.stats.finaltime as u64;
 
-        TETO_HTML_RECORDS_SPRINT
-            .replacen("{{sprint_time}}", &final_sprint_time, 1)
-            .replacen("{{sprint_time_ms}}", &ms.to_string(), 1)
-            .replacen("{{date}}", &ago, 1)
-            .replacen("{{country_ranking}}", &country_ranking, 1)
-            .replacen("{{global_ranking}}", &parse_blitz_score_number(sprint_record.rank as u64).to_string(), 1)
+    let duration = Duration::from_millis(sprint);
+    let ms = duration.subsec_millis();
+    let secs = duration.as_secs() % 60;
+    let minutes = duration.as_secs() / 60;
+    let hours = minutes / 60;
+    let hours_formated = if hours != 0 {
+        format!("{}:", hours)
     }
     else {
         String::new()
     };
-    
-    return Ok(result);
+
+    let minutes = if hours != 0 {
+        format!("{minutes:0width$}", width = 2)
+    }
+    else {
+        minutes.to_string()
+    };
+
+    let final_sprint_time = format!("{hours_formated}{}:{:0width$}", minutes, secs, width = 2);
+
+    let distinguishment = if sprint_record.rank == 1 {
+        Some(Distinguishment::champion("40l", "40 LINES CHAMPION"))
+    } else {
+        None
+    };
+
+    Ok(ParsedResultWithDistinguishment {
+        distinguishment,
+        result: Some(SprintRecord {
+            time: final_sprint_time,
+            time_ms: ms.to_string(),
+            ago,
+            global_ranking: parse_blitz_score_number(sprint_record.rank as u64),
+            country_ranking: parse_country_ranking(sprint_record.rank_local),
+        })
+    })
 }
 
+#[derive(Serialize, Default)]
+struct TetoRecords {
+    league_rating: Option<LeagueRatingRecord>,
+    league: Option<LeagueRecord>,
+    sprint: Option<SprintRecord>,
+    blitz: Option<BlitzRecord>,
+}
 
-struct TetoHTMLParams {
-    bad_standing: &'static str,
-    has_banner: bool,
-    banner: String,
+#[derive(Serialize, Default)]
+pub(crate) struct TetoHTMLParams {
+    bad_standing: bool,
+    banner: Option<String>,
     avatar: String,
     username: String,
-    flag: String,
+    flag: Option<String>,
     joined_at: String,
     friends: i64,
-    mod_badge: String,
-    distinguishment: String,
+    mod_badge: Option<String>,
+    distinguishment: Option<Distinguishment>,
     leveltag: String,
     level: u64,
-    game_time: String,
-    total_games: String,
-    supporter_badge: String,
-    badges: String,
-    records: String,
-}
-
-impl TetoHTMLParams {
-    fn into_html_page(self) -> String {
-        let TetoHTMLParams { bad_standing, has_banner, banner, avatar, username, flag, joined_at, friends, mod_badge, distinguishment, leveltag, level, game_time, total_games, supporter_badge, badges, records } = self;
-        TETO_HTML_FILE
-            .replacen("{{bad_standing}}", bad_standing.as_ref(), 1)
-            .replacen("{{has_banner}}", if has_banner {"has_banner"} else { "" }, 1)
-            .replacen("{{banner}}", banner.as_ref(), 1)
-            .replacen("{{avatar}}", &avatar, 1)
-            .replacen("{{username}}", &username, 1)
-            .replacen("{{flag}}", &flag.as_ref(), 1)
-            .replacen("{{joined_at}}", &joined_at, 1)
-            .replacen("{{friends}}", &friends.to_string(), 1)
-            .replacen("{{mod_badge}}", mod_badge.as_ref(), 1)
-            .replacen("{{distinguishment}}", &distinguishment, 1)
-            .replacen("{{leveltag}}", &leveltag, 1)
-            .replacen("{{level}}", &level.to_string(), 1)
-            .replacen("{{game_time}}", game_time.as_ref(), 1)
-            .replacen("{{total_games}}", total_games.as_ref(), 1)
-            .replacen("{{supporter_badge}}", supporter_badge.as_ref(), 1)
-            .replacen("{{badges}}", badges.as_ref(), 1)
-            .replacen("{{records}}", &records, 1)
-            .replace("{{country_flag}}", &flag)
-    }
-}
-
-pub(crate) async fn teto_test(State(state): State<Arc<AppState<'_>>>, Path(user_id): Path<String>) -> impl IntoResponse {
-    let client = state.api_http_client.as_ref();
-    let user = match client.fetch_user_info(&user_id).await {
+    game_time: Option<GameTime>,
+    total_games: Option<TotalGames>,
+    supporter_tier: Option<i64>,
+    badges: Vec<String>,
+    records: TetoRecords,
+}
+
+/// What a profile card is built from.
+struct TetoData {
+    user: Arc<Packet<UserInfo>>,
+    /// Banned users and bots have no records to show.
+    summaries: Option<Arc<Packet<AllSummaries>>>,
+}
+
+impl TetoData {
+    /// Identifies the `variant` of the card rendered from exactly this data; it changes whenever TETR.IO refreshes either packet.
+    fn page_key(&self, variant: &str) -> String {
+        let user_id = self.user.data.as_ref().map_or("", |data| &data.id);
+        let summaries_at = self.summaries.as_deref().map_or(0, page_cache::cached_at);
+        format!("teto:{variant}:{user_id}:{}:{summaries_at}", page_cache::cached_at(&self.user))
+    }
+}
+
+async fn fetch_teto_data(state: &AppState, user_id: &str) -> Result<TetoData, AppError> {
+    let user = match state.fetch_user_info(user_id).await {
         Ok(e) => e,
-        Err(e) => return Html(format!("<h1> Invalid user (1) {e:?} </h1>")).into_response()
+        Err(e) => return Err(AppError::Upstream(format!("Couldn't fetch user {user_id}: {e:?}")))
     };
-    let data = match user.data {
+    let data = match &user.data {
         Some(e) => e,
-        None => return Html("<h1> Invalid user (2) </h1>").into_response()
+        None => return Err(AppError::UserNotFound(format!("No user named {user_id}")))
+    };
+
+    if matches!(data.role, UserRole::Banned | UserRole::Bot) {
+        return Ok(TetoData { user, summaries: None });
+    }
+
+    let summaries = match state.fetch_user_summaries(&data.id).await {
+        Ok(v) => v,
+        Err(e) => return Err(AppError::Upstream(format!("Couldn't fetch summaries: {e:?}")))
+    };
+
+    Ok(TetoData { user, summaries: Some(summaries) })
+}
+
+fn build_teto_page(teto: &TetoData) -> Result<TetoPage, AppError> {
+    let data = match &teto.user.data {
+        Some(e) => e,
+        None => return Err(AppError::UserNotFound(String::from("No such user")))
     };
 
     if let UserRole::Banned = data.role {
-        return handle_banned(&data).await.into_response()
+        return Ok(handle_banned(data))
     }
 
     if let UserRole::Bot = data.role {
-        return handle_bot(&data).await.into_response();
+        return Ok(handle_bot(data));
     }
 
-    
+
     let distinguishment = None;
     let banner_rev = data.banner_revision.unwrap_or(0);
     let supporter_tier = data.supporter_tier;
     let has_banner = has_banner(banner_rev, supporter_tier);
     let banner = parse_banner(has_banner, banner_rev, &data.id);
-    let bad_standing = parse_bad_standing(data.badstanding);
+    let bad_standing = data.badstanding.unwrap_or(false);
     let avatar_rev = data.avatar_revision.unwrap_or(0);
     let avatar = parse_avatar(avatar_rev, &data.id);
     let mod_badge = parse_mod_badge(&data.role);
@@ -666,33 +633,33 @@ pub(crate) async fn teto_test(State(state): State<Arc<AppState<'_>>>, Path(user_
     let leveltag = parse_level_tag(level);
     let total_games = parse_total_games(data.gamesplayed, data.gameswon);
     let game_time = parse_gametime(data.gametime);
-    let supporter_badge = parse_supporter_badge(data.supporter_tier);
+    let supporter_tier = parse_supporter_tier(data.supporter_tier);
     let badges = parse_user_badges(&data.badges);
 
     let username = data.username.to_uppercase();
-    let summaries = match client.fetch_user_summaries(&data.id).await {
-        Ok(v) => v,
-        Err(_) => return Html("<h1> Couldn't get summaries! </h1>").into_response()
+    let summaries = match &teto.summaries {
+        Some(v) => v,
+        None => return Err(AppError::Upstream(String::from("TETR.IO returned no records for this user")))
     };
 
     let friends = data.friend_count.unwrap_or(0);
-    let joined_at = if let Some(ts) = data.ts {
-        let time = chrono::DateTime::<Utc>::from_str(&ts).unwrap_or_default();
+    let joined_at = if let Some(ts) = &data.ts {
+        let time = chrono::DateTime::<Utc>::from_str(ts).unwrap_or_default();
 
         let now = Utc::now();
         let duration = now.signed_duration_since(time);
 
         let ago = parse_duration_since(&duration);
 
-        String::from("JOINED ") + &ago + " AGO - "        
+        String::from("JOINED ") + &ago + " AGO - "
     }
     else {
         String::from("HERE SINCE THE BEGINNING - ")
     };
 
-    let data = match summaries.data {
+    let data = match &summaries.data {
         Some(v) => v,
-        None => return Html("<h1> No user records </h1>").into_response(),
+        None => return Err(AppError::Upstream(String::from("TETR.IO returned no records for this user"))),
     };
 
     let ParsedResultWithDistinguishment {distinguishment: league_distinguishment, result: league} = parse_tetra_league(&data.league);
@@ -700,39 +667,28 @@ pub(crate) async fn teto_test(State(state): State<Arc<AppState<'_>>>, Path(user_
 
 
 
-    let ParsedResultWithDistinguishment {distinguishment: sprint_distinguishment, result: sprint } = match parse_sprint(&data.sprint) {
-        Ok(result) => result,
-        Err(err) => return Html(err).into_response()
-    };
+    let ParsedResultWithDistinguishment {distinguishment: sprint_distinguishment, result: sprint } = parse_sprint(&data.sprint)?;
 
     let distinguishment = distinguishment.or(sprint_distinguishment);
-    
-    let ParsedResultWithDistinguishment { distinguishment: blitz_distinguishment, result: blitz} = match parse_blitz_score(&data.blitz) {
-        Ok(result) => result,
-        Err(err) => return Html(err).into_response()
-    };
 
-    let distinguishment = distinguishment.or(blitz_distinguishment);
+    let ParsedResultWithDistinguishment { distinguishment: blitz_distinguishment, result: blitz} = parse_blitz_score(&data.blitz)?;
 
-    let records = TETO_HTML_RECORDS
-        .replacen("{{tetra_league}}", &league, 1)
-        .replacen("{{sprint}}", &sprint, 1)
-        .replacen("{{blitz}}", blitz.as_ref(), 1);
+    let distinguishment = distinguishment.or(blitz_distinguishment);
 
-    let distinguishment = if let Some(staff_distinguishment) = staff_distinguishment{
-        staff_distinguishment
-    } else if let Some(distinguishment) = distinguishment {
-        distinguishment.to_string()
-    }
-    else {
-        String::new()
+    let LeagueRecords { rating: league_rating, ranked: league } = league.unwrap_or_default();
+    let records = TetoRecords {
+        league_rating,
+        league,
+        sprint,
+        blitz,
     };
 
+    let distinguishment = staff_distinguishment.or(distinguishment);
+
 
 
     let page = TetoHTMLParams {
         bad_standing,
-        has_banner,
         banner,
         avatar,
         username,
@@ -745,10 +701,158 @@ pub(crate) async fn teto_test(State(state): State<Arc<AppState<'_>>>, Path(user_
         level,
         game_time,
         total_games,
-        supporter_badge,
+        supporter_tier,
         badges,
         records,
     };
 
-    return Html(page.into_html_page()).into_response()
-}
\ No newline at end of file
+    Ok(TetoPage::Profile(Box::new(page)))
+}
+
+pub(crate) async fn teto_test(State(state): State<Arc<AppState>>, Path(user_id): Path<String>, headers: HeaderMap) -> Result<Response, AppError> {
+    let teto = fetch_teto_data(&state, &user_id).await?;
+    let page = state.page_cache.get_or_render(teto.page_key("html"), "text/html; charset=utf-8", || async {
+        Ok(build_teto_page(&teto)?.render("html")?.into_bytes())
+    }).await?;
+
+    Ok(page.respond(&headers))
+}
+
+/// `/api/teto/:user_id`
+pub(crate) async fn teto_json(State(state): State<Arc<AppState>>, Path(user_id): Path<String>, headers: HeaderMap) -> Result<Response, JsonError> {
+    let teto = fetch_teto_data(&state, &user_id).await?;
+    let page = state.page_cache.get_or_render(teto.page_key("json"), "application/json", || async {
+        serde_json::to_vec(&build_teto_page(&teto)?)
+            .map_err(|e| AppError::Render(format!("Couldn't serialize card: {e}")))
+    }).await?;
+
+    Ok(page.respond(&headers))
+}
+
+/// `/teto/:user_id.png`
+pub(crate) async fn teto_png(State(state): State<Arc<AppState>>, Path(file): Path<String>, headers: HeaderMap) -> Result<Response, AppError> {
+    let Some(user_id) = file.strip_suffix(".png") else {
+        return Err(AppError::NotFound(format!("No such file: {file}")))
+    };
+
+    let teto = fetch_teto_data(&state, user_id).await?;
+    let key = teto.page_key("png");
+    let page = state.page_cache.get_or_render(key.clone(), "image/png", || async {
+        let svg = build_teto_page(&teto)?.render("svg")?;
+
+        match state.png_renderer.render(&key, svg).await {
+            Ok(png) => Ok(png.as_ref().clone()),
+            Err(e) => Err(AppError::Render(format!("Couldn't render card: {e}")))
+        }
+    }).await?;
+
+    Ok(page.respond(&headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The page the handlers would build from what the mock TETR.IO answers for `name`.
+    fn fixture_page(name: &str) -> TetoPage {
+        let fixture = |path: &str| std::fs::read(format!("{}/tests/fixtures/tetrio/api/users/{path}.json", env!("CARGO_MANIFEST_DIR"))).ok();
+        let user = serde_json::from_slice(&fixture(name).expect("No such fixture")).expect("Not a user packet");
+        let summaries = fixture(&format!("{name}/summaries"))
+            .map(|summaries| Arc::new(serde_json::from_slice(&summaries).expect("Not a summaries packet")));
+
+        build_teto_page(&TetoData { user: Arc::new(user), summaries }).expect("Couldn't build the page")
+    }
+
+    #[test]
+    fn hostile_cards() {
+        templates::init().expect("Couldn't load the templates");
+
+        let profile = fixture_page("hostile").render("svg").expect("Couldn't render the card");
+        crate::tests::assert_escaped(&profile);
+        assert!(profile.contains("&lt;SCRIPT&gt;ALERT(&quot;X&quot;)&lt;&#x2f;SCRIPT&gt;&#x27;"), "{profile}");
+        assert!(profile.contains("&lt;script&gt;alert(3)&lt;&#x2f;script&gt; osk &quot;TETR.IO&quot; &#x27;FOUNDER&#x27;"), "{profile}");
+
+        let bot = fixture_page("hostilebot").render("svg").expect("Couldn't render the card");
+        crate::tests::assert_escaped(&bot);
+        assert!(bot.contains("&lt;SCRIPT&gt;ALERT(5)&lt;&#x2f;SCRIPT&gt;&quot;&#x27;"), "{bot}");
+    }
+
+    #[test]
+    fn blitz_score_number() {
+        let cases = [
+            (0, "0"),
+            (7, "7"),
+            (999, "999"),
+            (1_000, "1,000"),
+            (12_045, "12,045"),
+            (999_999, "999,999"),
+            (1_000_000, "1,000,000"),
+            (1_000_001, "1,000,001"),
+            (1_543_210, "1,543,210"),
+            (123_004_056, "123,004,056"),
+        ];
+
+        for (score, expected) in cases {
+            assert_eq!(parse_blitz_score_number(score), expected, "score {score}");
+        }
+    }
+
+    #[test]
+    fn duration_since() {
+        let cases = [
+            (chrono::Duration::seconds(1), "1 SECOND"),
+            (chrono::Duration::seconds(59), "59 SECONDS"),
+            (chrono::Duration::minutes(1), "1 MINUTE"),
+            (chrono::Duration::minutes(59), "59 MINUTES"),
+            (chrono::Duration::hours(1), "1 HOUR"),
+            (chrono::Duration::hours(23), "23 HOURS"),
+            (chrono::Duration::days(1), "1 DAY"),
+            (chrono::Duration::days(6), "6 DAYS"),
+            (chrono::Duration::days(7), "1 WEEK"),
+            (chrono::Duration::days(30), "4 WEEKS"),
+            (chrono::Duration::days(31), "1 MONTH"),
+            (chrono::Duration::days(59), "1 MONTH"),
+            (chrono::Duration::days(60), "2 MONTHS"),
+            (chrono::Duration::days(365), "12 MONTHS"),
+            (chrono::Duration::days(366), "1 YEAR"),
+            (chrono::Duration::days(730), "2 YEARS"),
+        ];
+
+        for (duration, expected) in cases {
+            assert_eq!(parse_duration_since(&duration), expected, "duration {duration}");
+        }
+    }
+
+    #[test]
+    fn level_tag() {
+        let cases = [
+            (1, "lt_shape_0 lt_badge_color_0 lt_shape_color_0"),
+            (10, "lt_shape_0 lt_badge_color_0 lt_shape_color_1"),
+            (123, "lt_shape_1 lt_badge_color_0 lt_shape_color_2"),
+            (500, "lt_shape_0 lt_badge_color_1 lt_shape_color_0"),
+            (4_999, "lt_shape_4 lt_badge_color_9 lt_shape_color_9"),
+            (5_000, "lt_golden"),
+            (5_001, "lt_shape_0 lt_badge_color_0 lt_shape_color_0"),
+        ];
+
+        for (level, expected) in cases {
+            assert_eq!(parse_level_tag(level), expected, "level {level}");
+        }
+    }
+
+    #[test]
+    fn level_from_xp_values() {
+        let cases = [
+            (0.0, 1),
+            (500.0, 2),
+            (1_500_000.0, 422),
+            (4_000_000.0, 1020),
+            (30_000_000.0, 3678),
+            (100_000_000.0, 5648),
+        ];
+
+        for (xp, expected) in cases {
+            assert_eq!(level_from_xp(xp) as u64, expected, "xp {xp}");
+        }
+    }
+}
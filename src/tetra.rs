@@ -1,19 +1,103 @@
 use std::sync::Arc;
 
-use axum::{response::{IntoResponse, Html, Response}, extract::{State, Query}};
+use axum::{response::{IntoResponse, Html, Response}, extract::{State, Query}, http::header, Json};
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use minijinja::{context, Value};
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use common::{LeagueRecord, Averages, Average, Round, Stats, LeagueRecordRequest};
-use itertools::Itertools;
-use reqwest::StatusCode;
 
-use crate::AppState;
+use crate::{chart::{round_seconds, ChartInput, RoundChart}, nerd_stats::{NerdStats, NerdStatsSpread}, error::{AppError, JsonError}, settings::MAX_RECORDS_LIMIT, templates::{self, Contract}, AppState, LeagueLeaderboard, GameReplayData, LeagueEndContextRound, LeagueRecordEntry};
 
 #[derive(Deserialize)]
 pub struct TetraParam {
     user_id: String,
-    game_num: usize
+    /// Parsed as a [`GameSelector`].
+    game_num: String
+}
+
+/// Which of a player's recent league matches to show.
+#[derive(Debug, PartialEq)]
+pub enum GameSelector {
+    /// `1` is the latest match.
+    Latest(usize),
+    /// `-1` is the oldest match still in the recent records.
+    Oldest(usize),
+    /// `latest win`
+    LatestWin,
+    /// `latest loss`
+    LatestLoss,
+    /// `latest vs <user>`, by id or username.
+    LatestVs(String),
+}
+
+impl std::str::FromStr for GameSelector {
+    type Err = AppError;
+
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        let out_of_range = || AppError::BadInput(format!(
+            "game_num must be between 1 and {MAX_RECORDS_LIMIT}, or between -{MAX_RECORDS_LIMIT} and -1 to count from the oldest match"
+        ));
+
+        let selector = selector.trim();
+        if let Ok(game_num) = selector.parse::<i64>() {
+            let index = game_num.unsigned_abs() as usize;
+            if index == 0 || index > MAX_RECORDS_LIMIT {
+                return Err(out_of_range());
+            }
+
+            return Ok(if game_num > 0 { GameSelector::Latest(index) } else { GameSelector::Oldest(index) });
+        }
+
+        let digits = selector.strip_prefix('-').unwrap_or(selector);
+        if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(out_of_range());
+        }
+
+        let lowercase = selector.to_lowercase().replace('_', " ");
+        let words = lowercase.strip_prefix("latest ").unwrap_or(&lowercase);
+        match words {
+            "win" => Ok(GameSelector::LatestWin),
+            "loss" => Ok(GameSelector::LatestLoss),
+            _ => match words.strip_prefix("vs ").map(str::trim) {
+                Some(opponent) if !opponent.is_empty() => Ok(GameSelector::LatestVs(opponent.to_string())),
+                _ => Err(AppError::BadInput(format!(
+                    "Unknown game_num {selector:?}, expected a number, \"latest win\", \"latest loss\" or \"latest vs <user>\""
+                ))),
+            },
+        }
+    }
+}
+
+impl GameSelector {
+    /// How many recent records need to be fetched to find the match.
+    fn records_needed(&self) -> usize {
+        match self {
+            GameSelector::Latest(index) => *index,
+            _ => MAX_RECORDS_LIMIT,
+        }
+    }
+
+    /// Whether `record` is the match a relative selector is looking for.
+    fn matches(&self, record: &LeagueRecordEntry) -> bool {
+        let player_id = &record.user.id;
+        let leaderboard = &record.leaderboard;
+        let Some(player) = leaderboard.iter().find(|entry| entry.is_player(player_id)) else {
+            return false;
+        };
+        let best_opponent = leaderboard.iter()
+            .filter(|entry| !entry.is_player(player_id))
+            .map(|entry| entry.wins)
+            .max()
+            .unwrap_or(0);
+
+        match self {
+            GameSelector::LatestWin => player.wins > best_opponent,
+            GameSelector::LatestLoss => player.wins < best_opponent,
+            GameSelector::LatestVs(opponent) => record.is_against(opponent),
+            GameSelector::Latest(_) | GameSelector::Oldest(_) => true,
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -28,19 +112,24 @@ pub struct ReplayParam {
     user_id: String
 }
 
-const TETRA_HTML_FILE: &str = include_str!("../assets/tetra/index.html");
-const TETRA_HTML_MATCH: &str = "<div class=\"multilog_result scroller_block zero\" data-hover=\"tap\" data-hit=\"click\">
-                        <div class=\"multilog_result_self {left_success}\"><span>{left_pps}</span> PPS - <span>{left_apm}</span> APM -
-                            <span>{left_vs}</span> VS</div>
-                        <div class=\"multilog_result_time\">{time}</div>
-                        <div class=\"multilog_result_opponent {right_success}\"><span>{right_pps}</span> PPS - <span>{right_apm}</span> APM -
-                            <span>{right_vs}</span> VS</div>
-                        </div>";
-const TETRA_EXTRA_HTML: &str = r#"<span>{pps}</span> PPS - <span>{apm}</span> APM -
-<span>{vs}</span> VS"#;
+pub(crate) fn template_contracts() -> Vec<Contract> {
+    let page = Value::from_serialize(TetraHtmlPage::default());
+
+    vec![
+        Contract::new("tetra/index.html", page.clone()),
+        Contract::new("tetra/partials/match.html", context! { round => TetraHtmlMatch::default(), ..page.clone() }),
+        Contract::new("tetra/partials/extra.html", context! { pps => 0.0, apm => 0.0, vs => 0.0, ..page.clone() }),
+        Contract::new("tetra/partials/nerd_stats.html", context! { stats => NerdStats::default(), ..page.clone() }),
+        Contract::new("tetra/partials/chart.html", page.clone()),
+        Contract::new("tetra/partials/spread.html", context! { spread => NerdStatsSpread::default(), ..page.clone() }),
+        Contract::new("tetra/index.svg", page),
+        Contract::new("tetra/multi.html", Value::from_serialize(TetraMultiPage::default())),
+        Contract::new("tetra/multi.svg", Value::from_serialize(TetraMultiPage::default())),
+    ]
+}
 
-#[derive(Default)]
-struct TetraHtmlMatch {
+#[derive(Serialize, Default)]
+pub struct TetraHtmlMatch {
     pub left_success: bool,
     pub left_pps: f64,
     pub left_apm: f64,
@@ -50,36 +139,8 @@ struct TetraHtmlMatch {
     pub right_pps: f64,
     pub right_apm: f64,
     pub right_vs: f64,
-}
-
-impl TetraHtmlMatch {
-
-
-    pub fn into_html_page(self) -> String {
-        // use replacen instead of replace to specify the number of replacements
-        let TetraHtmlMatch {
-            left_success,
-            left_pps,
-            left_apm,
-            left_vs,
-            time,
-            right_success,
-            right_pps,
-            right_apm,
-            right_vs,
-        } = self;
-
-        TETRA_HTML_MATCH
-            .replacen("{left_success}", if left_success { "success" } else { "" }, 1)
-            .replacen("{left_pps}", &format!("{:.2}", left_pps), 1)
-            .replacen("{left_apm}", &format!("{:.2}", left_apm), 1)
-            .replacen("{left_vs}", &format!("{:.2}", left_vs), 1)
-            .replacen("{time}", &time, 1)
-            .replacen("{right_success}", if right_success { "success" } else { "" } , 1)
-            .replacen("{right_pps}", &format!("{:.2}", right_pps), 1)
-            .replacen("{right_apm}", &format!("{:.2}", right_apm), 1)
-            .replacen("{right_vs}", &format!("{:.2}", right_vs), 1)
-    }
+    pub left_nerd_stats: NerdStats,
+    pub right_nerd_stats: NerdStats,
 }
 
 impl From<Round> for TetraHtmlMatch {
@@ -114,13 +175,15 @@ impl From<Round> for TetraHtmlMatch {
             right_pps,
             right_apm,
             right_vs,
+            left_nerd_stats: NerdStats::new(left_pps, left_apm, left_vs),
+            right_nerd_stats: NerdStats::new(right_pps, right_apm, right_vs),
         }
     }
 }
 
 
-#[derive(Default)]
-struct TetraHtmlPage {
+#[derive(Serialize, Default)]
+pub struct TetraHtmlPage {
     pub matches: Vec<TetraHtmlMatch>,
     pub left_username: String,
     pub right_username: String,
@@ -132,50 +195,20 @@ struct TetraHtmlPage {
     pub right_apm: f64,
     pub left_vs: f64,
     pub right_vs: f64,
+    pub left_nerd_stats: NerdStats,
+    pub right_nerd_stats: NerdStats,
+    /// Spread of each side's stats across rounds.
+    pub left_spread: NerdStatsSpread,
+    pub right_spread: NerdStatsSpread,
+    pub chart: RoundChart,
     pub played_date: String,
     pub played_time: String,
 }
 
 
 impl TetraHtmlPage {
-    pub fn into_html(self) -> String {
-        let TetraHtmlPage {
-            matches,
-            left_username,
-            right_username,
-            left_score,
-            right_score,
-            left_pps,
-            right_pps,
-            left_apm,
-            right_apm,
-            left_vs,
-            right_vs,
-            played_date,
-            played_time,
-        } = self;
-
-        let mut html = TETRA_HTML_FILE.to_string();
-        let left_extra = TETRA_EXTRA_HTML.replacen("{pps}", &format!("{:.2}", left_pps), 1)
-            .replacen("{apm}", &format!("{:.2}", left_apm), 1)
-            .replacen("{vs}", &format!("{:.2}", left_vs), 1);
-        let right_extra = TETRA_EXTRA_HTML.replacen("{pps}", &format!("{:.2}", right_pps), 1);
-        let right_extra = right_extra.replacen("{apm}", &format!("{:.2}", right_apm), 1);
-        let right_extra = right_extra.replacen("{vs}", &format!("{:.2}", right_vs), 1);
-
-
-        // Replace placeholders with actual values
-        html = html.replace("{left_username}", &left_username);
-        html = html.replace("{right_username}", &right_username);
-        html = html.replacen("{left_score}", &left_score.to_string(), 1);
-        html = html.replacen("{right_score}", &right_score.to_string(), 1);
-        html = html.replacen("{left_extra}", &left_extra, 1);
-        html = html.replacen("{right_extra}", &right_extra, 1);
-        html = html.replacen("{played_date}", &played_date, 1);
-        html = html.replacen("{played_time}", &played_time, 1);
-        html = html.replacen("{matches}", &matches.into_iter().map(|m| m.into_html_page()).join("\n"), 1);
-
-        html
+    pub fn into_html(self) -> Result<String, minijinja::Error> {
+        templates::render("tetra/index.html", &self)
     }
 
     pub fn from_league_record(league_record: LeagueRecord, timestamp: DateTime<Utc>) -> Self {
@@ -210,6 +243,16 @@ impl TetraHtmlPage {
         let played_time = timestamp.format("%H:%M:%S").to_string();
 
         let rounds = rounds.into_iter().map(|r| r.into()).collect::<Vec<TetraHtmlMatch>>();
+        let left_spread = NerdStatsSpread::new(&rounds.iter().map(|round| round.left_nerd_stats).collect::<Vec<_>>());
+        let right_spread = NerdStatsSpread::new(&rounds.iter().map(|round| round.right_nerd_stats).collect::<Vec<_>>());
+        let chart = RoundChart::new(rounds.iter().enumerate().map(|(index, round)| ChartInput {
+            label: format!("round {} - {}", index + 1, round.time),
+            weight: round_seconds(&round.time),
+            left: [round.left_pps, round.left_apm, round.left_vs],
+            right: [round.right_pps, round.right_apm, round.right_vs],
+            left_won: round.left_success,
+            right_won: round.right_success,
+        }).collect());
     
         Self {
             matches: rounds,
@@ -223,6 +266,11 @@ impl TetraHtmlPage {
             right_apm,
             left_vs,
             right_vs,
+            left_nerd_stats: NerdStats::new(left_pps, left_apm, left_vs),
+            right_nerd_stats: NerdStats::new(right_pps, right_apm, right_vs),
+            left_spread,
+            right_spread,
+            chart,
             played_date,
             played_time,
         }
@@ -230,14 +278,95 @@ impl TetraHtmlPage {
 }
 
 
+#[derive(Serialize, Default)]
+pub struct TetraMultiPlayer {
+    pub placement: usize,
+    pub username: String,
+    pub is_self: bool,
+    pub wins: i64,
+    pub pps: f64,
+    pub apm: f64,
+    pub vs: f64,
+}
+
+#[derive(Serialize, Default)]
+pub struct TetraMultiRoundPlayer {
+    pub placement: usize,
+    pub username: String,
+    pub is_self: bool,
+    pub alive: bool,
+    pub pps: f64,
+    pub apm: f64,
+    pub vs: f64,
+}
 
+#[derive(Serialize, Default)]
+pub struct TetraMultiRound {
+    pub time: String,
+    pub players: Vec<TetraMultiRoundPlayer>,
+}
 
-pub fn generate_league_recent(league_record: LeagueRecord, timestamp: DateTime<Utc>) -> String {
+/// Card for replays that don't have exactly two players, listing everyone by placement.
+#[derive(Serialize, Default)]
+pub struct TetraMultiPage {
+    pub players: Vec<TetraMultiPlayer>,
+    pub rounds: Vec<TetraMultiRound>,
+    pub played_date: String,
+    pub played_time: String,
+}
+
+/// A parsed replay, before it is turned into a card.
+pub enum LeagueReplay {
+    /// Exactly two players, `left` being the one the replay was requested for.
+    Duel(LeagueRecord),
+    Multi {
+        players: Vec<TetraMultiPlayer>,
+        rounds: Vec<TetraMultiRound>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TetraPage {
+    Duel(Box<TetraHtmlPage>),
+    Multi(TetraMultiPage),
+}
+
+impl TetraPage {
+    pub fn from_league_replay(league_replay: LeagueReplay, timestamp: DateTime<Utc>) -> Self {
+        match league_replay {
+            LeagueReplay::Duel(league_record) => TetraPage::Duel(Box::new(TetraHtmlPage::from_league_record(league_record, timestamp))),
+            LeagueReplay::Multi { players, rounds } => TetraPage::Multi(TetraMultiPage {
+                players,
+                rounds,
+                played_date: timestamp.format("%d/%m/%Y").to_string(),
+                played_time: timestamp.format("%H:%M:%S").to_string(),
+            }),
+        }
+    }
+
+    fn render(&self, extension: &str) -> Result<String, minijinja::Error> {
+        match self {
+            TetraPage::Duel(page) => templates::render(&format!("tetra/index.{extension}"), page),
+            TetraPage::Multi(page) => templates::render(&format!("tetra/multi.{extension}"), page),
+        }
+    }
+
+    fn into_html(self) -> Result<Html<String>, AppError> {
+        Ok(Html(self.render("html")?))
+    }
+}
+
+pub fn generate_league_recent(league_record: LeagueRecord, timestamp: DateTime<Utc>) -> Result<String, minijinja::Error> {
     TetraHtmlPage::from_league_record(league_record, timestamp).into_html()
 }
 
-pub async fn league_recent_test(Query(replay_data): Query<TetraTestParam>) -> impl IntoResponse {
-    Html(generate_league_recent(LeagueRecord 
+fn render_league_recent(league_record: LeagueRecord, timestamp: DateTime<Utc>) -> Result<Html<String>, AppError> {
+    Ok(Html(generate_league_recent(league_record, timestamp)?))
+}
+
+pub async fn league_recent_test(Query(replay_data): Query<TetraTestParam>) -> Result<Html<String>, AppError> {
+    render_league_recent(LeagueRecord 
         { 
             averages: Averages { 
                 left: Average {
@@ -257,7 +386,7 @@ pub async fn league_recent_test(Query(replay_data): Query<TetraTestParam>) -> im
             rounds: vec![]
         }, 
         chrono::offset::Utc::now()
-    )).into_response()
+    )
 }
 
 #[derive(Deserialize)]
@@ -265,61 +394,176 @@ pub struct LeagueReplayQuery {
     data: String
 }
 
-pub async fn league_replay_from_data(Query(data): Query<LeagueReplayQuery>) -> Response {
+fn parse_league_replay_data(data: LeagueReplayQuery) -> Result<(LeagueRecord, DateTime<Utc>), AppError> {
     let data = data.data;
     let Ok(data) = urlencoding::decode(&data) else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Couldn't decode data").into_response()
+        return Err(AppError::BadInput(String::from("Couldn't decode data")))
     };
 
-    let Ok(data) = serde_json::from_str::<LeagueRecordRequest>(&data) else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Couldn't parse data").into_response()
+    let data = match serde_json::from_str::<LeagueRecordRequest>(&data) {
+        Ok(data) => data,
+        Err(e) => return Err(AppError::BadInput(format!("Couldn't parse data: {e}")))
     };
 
     let LeagueRecordRequest {
         league_record,
         ts,
     } = data;
-    
-    Html(dbg!(generate_league_recent(league_record, DateTime::parse_from_rfc3339(&ts).unwrap_or_else(|_| chrono::offset::Utc::now().into()).with_timezone(&chrono::offset::Utc)))).into_response()
+
+    Ok((league_record, DateTime::parse_from_rfc3339(&ts).unwrap_or_else(|_| chrono::offset::Utc::now().into()).with_timezone(&chrono::offset::Utc)))
 }
 
-// basic handler that responds with a static string
-pub async fn league_recent(State(state): State<Arc<AppState<'_>>>, Query(user_id): Query<TetraParam>) -> Response {
-    let Ok(packet) = state.api_http_client.fetch_user_personal_league_records (&user_id.user_id, tetrio_api::http::parameters::personal_user_records::PersonalLeaderboard::Recent, tetrio_api::http::parameters::personal_user_records::PersonalRecordsQuery::NotBound { limit: Some(10) }).await else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Couldn't fetch or parse data").into_response()
+pub async fn league_replay_from_data(Query(data): Query<LeagueReplayQuery>) -> Result<Html<String>, AppError> {
+    let (league_record, timestamp) = parse_league_replay_data(data)?;
+    render_league_recent(league_record, timestamp)
+}
+
+/// `/api/league_replay_from_data`
+pub async fn league_replay_from_data_json(Query(data): Query<LeagueReplayQuery>) -> Result<Json<TetraPage>, JsonError> {
+    let (league_record, timestamp) = parse_league_replay_data(data)?;
+    Ok(Json(TetraPage::from_league_replay(LeagueReplay::Duel(league_record), timestamp)))
+}
+
+async fn fetch_league_recent(state: &AppState, user_id: &TetraParam) -> Result<(LeagueReplay, DateTime<Utc>), AppError> {
+    let selector = user_id.game_num.parse::<GameSelector>()?;
+
+    let packet = match state.tetrio_http_client.fetch_user_league_records(&user_id.user_id, LeagueLeaderboard::Recent, selector.records_needed() as i64).await {
+        Ok(packet) => packet,
+        Err(e) => return Err(AppError::Upstream(format!("Couldn't fetch recent records: {e:?}")))
     };
     
-    let Some(data) = packet.data else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Tetrio server error").into_response()
+    let Some(data) = &packet.data else {
+        return Err(AppError::UserNotFound(format!("No user named {}", user_id.user_id)))
     };
 
-    let Some(record) = data.entries.get(user_id.game_num - 1) else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "No recent records").into_response()
+    if data.entries.is_empty() {
+        return Err(AppError::NotFound(String::from("No recent records")))
+    }
+
+    let not_enough_records = || AppError::BadInput(format!("{} only has {} recent league matches", user_id.user_id, data.entries.len()));
+    let record = match selector {
+        GameSelector::Latest(index) => data.entries.get(index - 1).ok_or_else(not_enough_records)?,
+        GameSelector::Oldest(index) => data.entries.len().checked_sub(index).and_then(|index| data.entries.get(index)).ok_or_else(not_enough_records)?,
+        _ => data.entries.iter().find(|record| selector.matches(record)).ok_or_else(|| AppError::NotFound(
+            format!("No match for {:?} in the last {} league matches of {}", user_id.game_num, data.entries.len(), user_id.user_id)
+        ))?,
     };
 
-    generate_league_replay(state, &record.replayid, &user_id.user_id).await
+    // replays only know players by id, `user_id` may be a username
+    fetch_league_record(state, &record.replayid, &record.user.id).await
 }
 
-pub async fn league_replay(State(state): State<Arc<AppState<'_>>>, Query(replay_data): Query<ReplayParam>) -> Response {
-    generate_league_replay(state, &replay_data.replay_id, &replay_data.user_id).await
+// basic handler that responds with a static string
+pub async fn league_recent(State(state): State<Arc<AppState>>, Query(user_id): Query<TetraParam>) -> Result<Html<String>, AppError> {
+    let (league_replay, timestamp) = fetch_league_recent(&state, &user_id).await?;
+    TetraPage::from_league_replay(league_replay, timestamp).into_html()
 }
 
-async fn generate_league_replay(state: Arc<AppState<'_>>, replay_id: &str, user_id: &str) -> Response {
-    
-    let replay_data = state.tetrio_http_client.fetch_tetrio_replay(replay_id, &state.tetrio_token).await;
-    dbg!(&replay_data);
-    let replay_data = match replay_data {
+/// `/api/league_recent`
+pub async fn league_recent_json(State(state): State<Arc<AppState>>, Query(user_id): Query<TetraParam>) -> Result<Json<TetraPage>, JsonError> {
+    let (league_replay, timestamp) = fetch_league_recent(&state, &user_id).await?;
+    Ok(Json(TetraPage::from_league_replay(league_replay, timestamp)))
+}
+
+pub async fn league_replay(State(state): State<Arc<AppState>>, Query(replay_data): Query<ReplayParam>) -> Result<Html<String>, AppError> {
+    let (league_replay, timestamp) = fetch_league_record(&state, &replay_data.replay_id, &replay_data.user_id).await?;
+    TetraPage::from_league_replay(league_replay, timestamp).into_html()
+}
+
+/// `/api/league_replay`
+pub async fn league_replay_json(State(state): State<Arc<AppState>>, Query(replay_data): Query<ReplayParam>) -> Result<Json<TetraPage>, JsonError> {
+    let (league_replay, timestamp) = fetch_league_record(&state, &replay_data.replay_id, &replay_data.user_id).await?;
+    Ok(Json(TetraPage::from_league_replay(league_replay, timestamp)))
+}
+
+/// `/league_replay.png`
+pub async fn league_replay_png(State(state): State<Arc<AppState>>, Query(replay_data): Query<ReplayParam>) -> Result<Response, AppError> {
+    let (league_replay, timestamp) = fetch_league_record(&state, &replay_data.replay_id, &replay_data.user_id).await?;
+
+    let svg = TetraPage::from_league_replay(league_replay, timestamp).render("svg")?;
+
+    let key = format!("league_replay:{}:{}", replay_data.replay_id, replay_data.user_id);
+    match state.png_renderer.render(&key, svg).await {
+        Ok(png) => Ok(([(header::CONTENT_TYPE, "image/png")], png.as_ref().clone()).into_response()),
+        Err(e) => Err(AppError::Render(format!("Couldn't render card: {e}")))
+    }
+}
+
+async fn fetch_league_record(state: &AppState, replay_id: &str, user_id: &str) -> Result<(LeagueReplay, DateTime<Utc>), AppError> {
+    let replay_data = match state.tetrio_http_client.fetch_tetrio_replay(replay_id, &state.settings.tetrio.token).await {
         Ok(replay_data) => replay_data,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("Couldn't fetch replay data: {e}")).into_response(),
+        Err(e) => return Err(AppError::Upstream(format!("Couldn't fetch replay data: {e}"))),
     };
 
     let Some(data) = &replay_data.game else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Couldn't fetch replay data (game)").into_response()
+        return Err(AppError::NotFound(format!("No replay with id {replay_id}")))
     };
 
+    Ok((generate_league_replay(data, replay_id, user_id)?, data.ts))
+}
+
+fn round_time(round: &[LeagueEndContextRound]) -> String {
+    let frame = round.iter().map(|f| f.lifetime).max().unwrap_or(0);
+    let frames = frame as u64;
+
+    let duration = Duration::from_millis(frames);
+    
+    let minutes = duration.as_secs() / 60;
+    let seconds = duration.as_secs() % 60;
+
+    format!("{minutes}:{seconds:02}")
+}
+
+/// Ranks every player of a replay that isn't a 1v1.
+///
+/// The leaderboard is ordered by rounds won, and each round by who survived, then by who survived the longest.
+fn generate_multi_replay(data: &GameReplayData, user_id: &str) -> LeagueReplay {
+    let mut leaderboard = data.results.leaderboard.iter().collect::<Vec<_>>();
+    leaderboard.sort_by_key(|entry| std::cmp::Reverse(entry.wins));
+
+    let players = leaderboard.into_iter().enumerate().map(|(index, entry)| {
+        TetraMultiPlayer {
+            placement: index + 1,
+            username: entry.get_username().unwrap_or_default(),
+            is_self: entry.is_player(user_id),
+            wins: entry.wins,
+            pps: entry.stats.pps,
+            apm: entry.stats.apm,
+            vs: entry.stats.vsscore,
+        }
+    }).collect();
+
+    let rounds = data.results.rounds.iter().map(|round| {
+        let mut participants = round.iter().collect::<Vec<_>>();
+        participants.sort_by_key(|participant| (std::cmp::Reverse(participant.alive), std::cmp::Reverse(participant.lifetime)));
+
+        TetraMultiRound {
+            time: round_time(round),
+            players: participants.into_iter().enumerate().map(|(index, participant)| {
+                TetraMultiRoundPlayer {
+                    placement: index + 1,
+                    username: participant.username.clone().unwrap_or_default(),
+                    is_self: participant.is_player(user_id),
+                    alive: participant.alive,
+                    pps: participant.stats.pps,
+                    apm: participant.stats.apm,
+                    vs: participant.stats.vsscore,
+                }
+            }).collect(),
+        }
+    }).collect();
+
+    LeagueReplay::Multi { players, rounds }
+}
+
+pub(crate) fn generate_league_replay(data: &GameReplayData, replay_id: &str, user_id: &str) -> Result<LeagueReplay, AppError> {
+    if data.results.leaderboard.len() != 2 {
+        return Ok(generate_multi_replay(data, user_id));
+    }
+
     let (Some(left), Some(right)) = (        
-        data.results.leaderboard.iter().find(|f| f.id.clone().unwrap_or("".into()) == user_id), data.results.leaderboard.iter().find(|f| f.id.clone().unwrap_or("".into()) != user_id)) else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to parse data (couldn't find end contexts)").into_response()
+        data.results.leaderboard.iter().find(|f| f.is_player(user_id)), data.results.leaderboard.iter().find(|f| !f.is_player(user_id))) else {
+        return Err(AppError::MissingPlayer(format!("Replay {replay_id} has no player {user_id} and an opponent")))
     };
     
 
@@ -341,16 +585,8 @@ async fn generate_league_replay(state: Arc<AppState<'_>>, replay_id: &str, user_
             }
         },
         rounds: data.results.rounds.iter().filter_map(|data| {
-            let frame = data.iter().map(|f| f.lifetime).max().unwrap_or(0);
-            let frames = frame as u64;
-
-            let duration = Duration::from_millis(frames as u64);
-            
-            let minutes = duration.as_secs() / 60;
-            let seconds = duration.as_secs() % 60;
-
-            let left = data.iter().find(|f| f.id.clone().unwrap_or(String::new()) == user_id);
-            let right = data.iter().find(|f| f.id.clone().unwrap_or(String::new()) != user_id);
+            let left = data.iter().find(|f| f.is_player(user_id));
+            let right = data.iter().find(|f| !f.is_player(user_id));
 
             if let (Some(left), Some(right)) = (left, right) {
 
@@ -367,7 +603,7 @@ async fn generate_league_replay(state: Arc<AppState<'_>>, replay_id: &str, user_
                         vs: right.stats.vsscore,
                         success: right.alive
                     }, 
-                    time: format!("{minutes}:{seconds:02}")
+                    time: round_time(data)
                 
                 })
             } else {
@@ -376,5 +612,5 @@ async fn generate_league_replay(state: Arc<AppState<'_>>, replay_id: &str, user_
         }).collect(),
     };
 
-    Html(dbg!(generate_league_recent(league_record, data.ts))).into_response()
+    Ok(LeagueReplay::Duel(league_record))
 }
@@ -0,0 +1,366 @@
+use std::{future::Future, sync::{Arc, Mutex, OnceLock}, time::{Duration, Instant}};
+
+use axum::body::Bytes;
+use moka::future::Cache;
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+
+use crate::metrics::metrics;
+
+/// Where TETR.IO lives, overridable so the server can be pointed at a mock (see `src/bin/mock_tetrio.rs`).
+///
+/// Set once at startup from the settings, and have no trailing slash.
+pub struct TetrioUrls {
+    /// The main API, used for replays and the logged in user.
+    pub api: String,
+    /// The public channel API, used for users, summaries and records.
+    pub ch_api: String,
+    /// Where `/res`, `/css` and `/user-content` are served from.
+    pub cdn: String,
+}
+
+impl Default for TetrioUrls {
+    fn default() -> Self {
+        Self {
+            api: String::from("https://tetr.io/api"),
+            ch_api: String::from("https://ch.tetr.io/api"),
+            cdn: String::from("https://tetr.io"),
+        }
+    }
+}
+
+static URLS: OnceLock<TetrioUrls> = OnceLock::new();
+
+/// Sets where TETR.IO lives; does nothing if the urls were already set or read.
+pub fn init_urls(urls: TetrioUrls) {
+    let _ = URLS.set(urls);
+}
+
+pub fn urls() -> &'static TetrioUrls {
+    URLS.get_or_init(TetrioUrls::default)
+}
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// TETR.IO asks bots to stay around one request per second, with short bursts being fine.
+pub const REQUESTS_PER_SECOND: f64 = 1.0;
+pub const BURST: f64 = 5.0;
+
+/// The CDN serves static files, and a card can need a handful of them at once.
+const ASSET_REQUESTS_PER_SECOND: f64 = 10.0;
+const ASSET_BURST: f64 = 20.0;
+
+const MAX_RETRIES: u32 = 3;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+const BREAKER_THRESHOLD: u32 = 5;
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Spaces outbound requests out, letting a few through at once.
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    state: tokio::sync::Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, capacity: f64) -> Self {
+        Self { rate, capacity, state: tokio::sync::Mutex::new((capacity, Instant::now())) }
+    }
+
+    /// Waits until a request may be sent.
+    pub async fn acquire(&self) {
+        // holding the lock while sleeping keeps waiting requests in order
+        let mut state = self.state.lock().await;
+        let (tokens, last) = &mut *state;
+
+        let now = Instant::now();
+        *tokens = f64::min(self.capacity, *tokens + now.duration_since(*last).as_secs_f64() * self.rate);
+        *last = now;
+
+        if *tokens < 1.0 {
+            tokio::time::sleep(Duration::from_secs_f64((1.0 - *tokens) / self.rate)).await;
+            *tokens = 1.0;
+            *last = Instant::now();
+        }
+
+        *tokens -= 1.0;
+    }
+}
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Stops calling TETR.IO for a while after it failed several times in a row.
+///
+/// Once the cooldown is over a single request is let through; it closes the breaker again if it succeeds.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self { threshold, cooldown, state: Mutex::default() }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, BreakerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether a request may be sent right now.
+    pub fn allow(&self) -> bool {
+        let mut state = self.state();
+        match state.open_until {
+            Some(open_until) if Instant::now() < open_until => false,
+            Some(_) => {
+                // half open: let this one through, and keep the others out until it is done
+                state.open_until = Some(Instant::now() + self.cooldown);
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.state() = BreakerState::default();
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state();
+        state.failures += 1;
+        if state.failures >= self.threshold {
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CircuitOpen;
+
+impl std::fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TETR.IO has been failing, not calling it for now")
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+/// How long TETR.IO asked us to wait, either in seconds or as a date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+fn should_retry(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Runs `request`, keeping its answer in `stale` and falling back to it when TETR.IO is failing.
+pub async fn with_stale_fallback<V, F>(stale: &Cache<Box<str>, V>, key: &str, request: F) -> Result<V, Arc<anyhow::Error>>
+where
+    V: Clone + Send + Sync + 'static,
+    F: Future<Output = anyhow::Result<V>>,
+{
+    match request.await {
+        Ok(value) => {
+            stale.insert(key.into(), value.clone()).await;
+            Ok(value)
+        }
+        Err(e) => stale.get(key).await.ok_or_else(|| Arc::new(e)),
+    }
+}
+
+/// How an attempt went, as reported by the `upstream_requests_total` metric.
+fn status_outcome(status: StatusCode) -> &'static str {
+    match status {
+        status if status.is_success() => "ok",
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => "unauthorized",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+        status if status.is_client_error() => "client_error",
+        status if status.is_server_error() => "server_error",
+        _ => "other",
+    }
+}
+
+fn error_outcome(error: &reqwest::Error) -> &'static str {
+    if error.is_timeout() {
+        "timeout"
+    } else if error.is_connect() {
+        "connect"
+    } else {
+        "transport"
+    }
+}
+
+/// The one HTTP client every call to TETR.IO made by this server goes through.
+pub struct UpstreamClient {
+    /// `client` label of the metrics.
+    name: &'static str,
+    http: reqwest::Client,
+    accept: &'static str,
+    limiter: TokenBucket,
+    breaker: CircuitBreaker,
+    last_success: Mutex<Option<Instant>>,
+}
+
+impl UpstreamClient {
+    fn new(name: &'static str, accept: &'static str, limiter: TokenBucket) -> Self {
+        Self {
+            name,
+            http: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            accept,
+            limiter,
+            breaker: CircuitBreaker::new(BREAKER_THRESHOLD, BREAKER_COOLDOWN),
+            last_success: Mutex::new(None),
+        }
+    }
+
+    /// A client for the API, sending `requests_per_second` on average.
+    pub fn for_api(requests_per_second: f64, burst: f64) -> Self {
+        Self::new("api", "application/json", TokenBucket::new(requests_per_second, burst))
+    }
+
+    /// A client for the CDN, with its own rate limit and breaker.
+    pub fn for_assets() -> Self {
+        Self::new("assets", "*/*", TokenBucket::new(ASSET_REQUESTS_PER_SECOND, ASSET_BURST))
+    }
+
+    /// When TETR.IO last answered with something other than an error, 404s included.
+    pub fn last_success(&self) -> Option<Instant> {
+        *self.last_success.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A single `GET` giving up after `timeout`, for health checks.
+    ///
+    /// It skips the rate limit and the breaker: a probe shouldn't wait behind pages, nor be turned away by them.
+    pub async fn probe(&self, url: &str, tetrio_token: Option<&str>, timeout: Duration) -> anyhow::Result<(StatusCode, Bytes)> {
+        let mut request = self.http.get(url).header("Accept", self.accept).timeout(timeout);
+        if let Some(tetrio_token) = tetrio_token {
+            request = request.header("Authorization", tetrio_token);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        if status.is_success() || status == StatusCode::NOT_FOUND {
+            *self.last_success.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        }
+        Ok((status, body))
+    }
+
+    /// `GET`s `url`, retrying on rate limits, server errors and timeouts.
+    ///
+    /// The token is only needed by the main API, the channel API is public.
+    pub async fn get(&self, url: &str, tetrio_token: Option<&str>) -> anyhow::Result<Bytes> {
+        self.get_with_status(url, tetrio_token).await.map(|(_, body)| body)
+    }
+
+    /// Like [`UpstreamClient::get`], for callers that care about client errors such as a 404.
+    pub async fn get_with_status(&self, url: &str, tetrio_token: Option<&str>) -> anyhow::Result<(StatusCode, Bytes)> {
+        if !self.breaker.allow() {
+            metrics().record_upstream(self.name, "circuit_open");
+            return Err(CircuitOpen.into());
+        }
+
+        let mut attempt = 0;
+        loop {
+            self.limiter.acquire().await;
+
+            let mut request = self.http.get(url).header("Accept", self.accept);
+            if let Some(tetrio_token) = tetrio_token {
+                request = request.header("Authorization", tetrio_token);
+            }
+            let response = request.send().await;
+
+            let backoff = BASE_BACKOFF.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_BACKOFF);
+            let (error, delay) = match response {
+                Ok(response) if should_retry(response.status()) => {
+                    metrics().record_upstream(self.name, status_outcome(response.status()));
+                    let delay = retry_after(&response).unwrap_or(backoff);
+                    (anyhow::anyhow!("TETR.IO answered {}", response.status()), delay)
+                }
+                Ok(response) => match (response.status(), response.bytes().await) {
+                    (status, Ok(body)) => {
+                        metrics().record_upstream(self.name, status_outcome(status));
+                        self.breaker.record_success();
+                        if status.is_success() || status == StatusCode::NOT_FOUND {
+                            *self.last_success.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+                        }
+                        return Ok((status, body));
+                    }
+                    (_, Err(e)) => {
+                        metrics().record_upstream(self.name, error_outcome(&e));
+                        (e.into(), backoff)
+                    }
+                },
+                Err(e) => {
+                    metrics().record_upstream(self.name, error_outcome(&e));
+                    (e.into(), backoff)
+                }
+            };
+
+            if attempt >= MAX_RETRIES || delay > MAX_BACKOFF {
+                self.breaker.record_failure();
+                return Err(error);
+            }
+
+            attempt += 1;
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn breaker_opens_after_the_threshold() {
+        let breaker = CircuitBreaker::new(2, COOLDOWN);
+
+        breaker.record_failure();
+        assert!(breaker.allow());
+        breaker.record_failure();
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn breaker_half_opens_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(1, COOLDOWN);
+        breaker.record_failure();
+        std::thread::sleep(COOLDOWN);
+
+        // a single request goes through
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+
+        // and opens the breaker again when it fails
+        breaker.record_failure();
+        assert!(!breaker.allow());
+        std::thread::sleep(COOLDOWN);
+
+        // or closes it when it succeeds
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert!(breaker.allow());
+        assert!(breaker.allow());
+    }
+}